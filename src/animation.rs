@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use bevy::gltf::Gltf;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::species::Species;
+use crate::{GameResources, PartyAnimal, Player, CHARACTER_SPEED};
+
+const IDLE_SPEED: f32 = 0.5;
+const RUN_SPEED: f32 = CHARACTER_SPEED * 0.6;
+const JUMP_SPEED: f32 = 2.;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AnimationState {
+    Idle,
+    Walk,
+    Run,
+    Jump,
+    Dance,
+}
+
+#[derive(Component, Default)]
+pub struct AnimalAnimator {
+    state: Option<AnimationState>,
+}
+
+// Clips are authored in Blender as "<Species>.<State>" actions, eg. "Bunny.Walk", and fall
+// back to a shared "<State>" action.
+pub struct AnimationLibrary {
+    gltf: Handle<Gltf>,
+    // Clips by name with the root's own curves taken out. Bevy skips the first part of a curve's
+    // path, the player entity stands in for it, so every animal can share the same copy.
+    stripped: HashMap<String, Handle<AnimationClip>>,
+}
+
+pub fn load_animation_library(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_resources: Res<GameResources>,
+) {
+    commands.insert_resource(AnimationLibrary {
+        gltf: asset_server.load(&format!("{}.gltf", game_resources.current_level)),
+        stripped: HashMap::new(),
+    });
+}
+
+fn strip_root(clip: &AnimationClip) -> AnimationClip {
+    let mut stripped = AnimationClip::default();
+    for (path, curves) in clip.curves() {
+        // Curves on the root itself would fight with rapier over the transform
+        if path.parts.len() < 2 {
+            continue;
+        }
+        for curve in curves {
+            stripped.add_curve_to_path(path.clone(), curve.clone());
+        }
+    }
+    stripped
+}
+
+fn target_state(velocity: &Velocity, is_partying: bool) -> AnimationState {
+    let horizontal_speed = Vec2::new(velocity.linvel.x, velocity.linvel.z).length();
+    if is_partying {
+        AnimationState::Dance
+    } else if velocity.linvel.y.abs() > JUMP_SPEED {
+        AnimationState::Jump
+    } else if horizontal_speed < IDLE_SPEED {
+        AnimationState::Idle
+    } else if horizontal_speed < RUN_SPEED {
+        AnimationState::Walk
+    } else {
+        AnimationState::Run
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn animate_animals(
+    gltfs: Res<Assets<Gltf>>,
+    mut clips: ResMut<Assets<AnimationClip>>,
+    // Only there once a level has started loading
+    library: Option<ResMut<AnimationLibrary>>,
+    mut animals: Query<(
+        &Species,
+        &Velocity,
+        &mut AnimalAnimator,
+        &mut AnimationPlayer,
+        Option<&Player>,
+        Option<&PartyAnimal>,
+    )>,
+) {
+    let mut library = match library {
+        Some(library) => library,
+        None => return,
+    };
+    let gltf = match gltfs.get(&library.gltf) {
+        Some(gltf) => gltf,
+        None => return,
+    };
+    for (species, velocity, mut animator, mut player, is_player, is_partying) in animals.iter_mut()
+    {
+        let state = target_state(velocity, is_partying.is_some());
+        if is_player.is_some() && matches!(state, AnimationState::Walk | AnimationState::Run) {
+            let horizontal_speed = Vec2::new(velocity.linvel.x, velocity.linvel.z).length();
+            player.set_speed(horizontal_speed / RUN_SPEED);
+        } else {
+            player.set_speed(1.);
+        }
+        if animator.state == Some(state) {
+            continue;
+        }
+        let clip_name = [format!("{:?}.{:?}", species, state), format!("{:?}", state)]
+            .into_iter()
+            .find(|clip_name| gltf.named_animations.contains_key(clip_name));
+        let clip_name = match clip_name {
+            Some(clip_name) => clip_name,
+            None => continue,
+        };
+        let handle = match library.stripped.get(&clip_name) {
+            Some(handle) => handle.clone(),
+            None => match clips
+                .get(&gltf.named_animations[&clip_name])
+                .map(strip_root)
+            {
+                Some(clip) => {
+                    let handle = clips.add(clip);
+                    library.stripped.insert(clip_name, handle.clone());
+                    handle
+                }
+                None => continue,
+            },
+        };
+        player.play(handle).repeat();
+        animator.state = Some(state);
+    }
+}
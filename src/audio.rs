@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use bevy::audio::AudioSink;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::gentle::Cheer;
+use crate::species::Species;
+use crate::{AnimalFell, AnimalRecruited, GameResources, PartyAnimal, Player};
+
+const FOOTSTEP_STRIDE: f32 = 3.;
+const PARTY_LOOP_FULL_COUNT: f32 = 10.;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum MixerBus {
+    Music,
+    Effects,
+    Ambience,
+}
+
+pub struct AudioMixer {
+    pub master: f32,
+    pub buses: HashMap<MixerBus, f32>,
+}
+
+impl Default for AudioMixer {
+    fn default() -> Self {
+        AudioMixer {
+            master: 1.,
+            buses: HashMap::from([
+                (MixerBus::Music, 0.6),
+                (MixerBus::Effects, 1.),
+                (MixerBus::Ambience, 0.8),
+            ]),
+        }
+    }
+}
+
+impl AudioMixer {
+    pub fn volume(&self, bus: MixerBus) -> f32 {
+        self.master * self.buses.get(&bus).copied().unwrap_or(1.)
+    }
+}
+
+pub struct AudioAssets {
+    footstep: Handle<AudioSource>,
+    fall: Handle<AudioSource>,
+    cheer: Handle<AudioSource>,
+    party_loop: Handle<AudioSource>,
+    collected: HashMap<Species, Handle<AudioSource>>,
+}
+
+#[derive(Default)]
+pub struct AudioChannels {
+    music: Option<Handle<AudioSink>>,
+    party: Option<Handle<AudioSink>>,
+}
+
+pub fn load_audio(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(AudioAssets {
+        footstep: asset_server.load("audio/footstep.wav"),
+        fall: asset_server.load("audio/fall.wav"),
+        cheer: asset_server.load("audio/cheer.wav"),
+        party_loop: asset_server.load("audio/party_loop.wav"),
+        collected: [
+            Species::Puppy,
+            Species::Kitty,
+            Species::Bunny,
+            Species::Unknown,
+        ]
+        .iter()
+        .map(|&species| {
+            (
+                species,
+                asset_server.load(&format!("audio/collect_{}.wav", species.label())),
+            )
+        })
+        .collect(),
+    });
+}
+
+pub fn start_level_audio(
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    asset_server: Res<AssetServer>,
+    audio_assets: Res<AudioAssets>,
+    mixer: Res<AudioMixer>,
+    game_resources: Res<GameResources>,
+    mut channels: ResMut<AudioChannels>,
+) {
+    let music = asset_server.load(&format!("audio/music/{}.wav", game_resources.current_level));
+    channels.music = Some(audio_sinks.get_handle(audio.play_with_settings(
+        music,
+        PlaybackSettings::LOOP.with_volume(mixer.volume(MixerBus::Music)),
+    )));
+    // Starts silent, `party_audio` fades it in as animals arrive
+    channels.party = Some(audio_sinks.get_handle(audio.play_with_settings(
+        audio_assets.party_loop.clone(),
+        PlaybackSettings::LOOP.with_volume(0.),
+    )));
+}
+
+pub fn stop_level_audio(audio_sinks: Res<Assets<AudioSink>>, mut channels: ResMut<AudioChannels>) {
+    for handle in [channels.music.take(), channels.party.take()]
+        .into_iter()
+        .flatten()
+    {
+        if let Some(sink) = audio_sinks.get(&handle) {
+            sink.stop();
+        }
+    }
+}
+
+pub fn party_audio(
+    audio_sinks: Res<Assets<AudioSink>>,
+    mixer: Res<AudioMixer>,
+    channels: Res<AudioChannels>,
+    party_animals: Query<(), With<PartyAnimal>>,
+) {
+    let intensity = (party_animals.iter().count() as f32 / PARTY_LOOP_FULL_COUNT).min(1.);
+    if let Some(sink) = channels
+        .party
+        .as_ref()
+        .and_then(|handle| audio_sinks.get(handle))
+    {
+        sink.set_volume(intensity * mixer.volume(MixerBus::Ambience));
+    }
+    if let Some(sink) = channels
+        .music
+        .as_ref()
+        .and_then(|handle| audio_sinks.get(handle))
+    {
+        sink.set_volume(mixer.volume(MixerBus::Music));
+    }
+}
+
+pub fn gameplay_sounds(
+    audio: Res<Audio>,
+    audio_assets: Res<AudioAssets>,
+    mixer: Res<AudioMixer>,
+    mut recruited: EventReader<AnimalRecruited>,
+    mut fell: EventReader<AnimalFell>,
+    mut cheers: EventReader<Cheer>,
+) {
+    let volume = mixer.volume(MixerBus::Effects);
+    for event in recruited.iter() {
+        if let Some(sound) = audio_assets.collected.get(&event.species) {
+            audio.play_with_settings(sound.clone(), PlaybackSettings::ONCE.with_volume(volume));
+        }
+    }
+    for _ in fell.iter() {
+        audio.play_with_settings(
+            audio_assets.fall.clone(),
+            PlaybackSettings::ONCE.with_volume(volume),
+        );
+    }
+    // The whole herd cheers together, one sound is plenty
+    if cheers.iter().count() > 0 {
+        audio.play_with_settings(
+            audio_assets.cheer.clone(),
+            PlaybackSettings::ONCE.with_volume(volume),
+        );
+    }
+}
+
+pub fn footsteps(
+    time: Res<Time>,
+    audio: Res<Audio>,
+    audio_assets: Res<AudioAssets>,
+    mixer: Res<AudioMixer>,
+    mut stride: Local<f32>,
+    players: Query<&Velocity, With<Player>>,
+) {
+    // One footstep track for the whole herd, otherwise a big herd turns into a drumroll
+    let speed = players
+        .iter()
+        .map(|velocity| Vec2::new(velocity.linvel.x, velocity.linvel.z).length())
+        .fold(0., f32::max);
+    *stride += speed * time.delta_seconds();
+    if *stride > FOOTSTEP_STRIDE {
+        *stride = 0.;
+        audio.play_with_settings(
+            audio_assets.footstep.clone(),
+            PlaybackSettings::ONCE.with_volume(mixer.volume(MixerBus::Effects) * 0.5),
+        );
+    }
+}
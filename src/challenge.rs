@@ -0,0 +1,231 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::levels::{LevelManifest, Levels};
+use crate::outcome::LevelClock;
+use crate::save::{self, Progress};
+use crate::GameResources;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum Medal {
+    Bronze,
+    Silver,
+    Gold,
+}
+
+impl Medal {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Medal::Bronze => "Bronze",
+            Medal::Silver => "Silver",
+            Medal::Gold => "Gold",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct MedalThreshold {
+    // Par time in seconds
+    pub time: f32,
+    // Animals delivered to the party plus animals brought home
+    pub animals: i32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct MedalThresholds {
+    pub bronze: MedalThreshold,
+    pub silver: MedalThreshold,
+    pub gold: MedalThreshold,
+}
+
+impl MedalThresholds {
+    // Best medal first
+    fn iter(&self) -> [(Medal, MedalThreshold); 3] {
+        [
+            (Medal::Gold, self.gold),
+            (Medal::Silver, self.silver),
+            (Medal::Bronze, self.bronze),
+        ]
+    }
+
+    pub fn medal(&self, elapsed: f32, animals: i32) -> Option<Medal> {
+        self.iter()
+            .into_iter()
+            .find(|(_, threshold)| elapsed <= threshold.time && animals >= threshold.animals)
+            .map(|(medal, _)| medal)
+    }
+}
+
+pub struct ChallengeSettings {
+    pub timed: bool,
+}
+
+impl Default for ChallengeSettings {
+    fn default() -> Self {
+        ChallengeSettings {
+            timed: std::env::args().any(|arg| arg == "--timed"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ChallengeResult {
+    pub medal: Option<Medal>,
+    pub new_record: bool,
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct AwardMedal;
+
+#[derive(Component)]
+pub struct Countdown {
+    thresholds: MedalThresholds,
+}
+
+fn current_thresholds(
+    levels: &Levels,
+    manifests: &Assets<LevelManifest>,
+    game_resources: &GameResources,
+) -> Option<MedalThresholds> {
+    levels.get(manifests, &game_resources.current_level)?.medals
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds.max(0.).ceil() as i32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_challenge(
+    mut commands: Commands,
+    settings: Res<ChallengeSettings>,
+    levels: Res<Levels>,
+    manifests: Res<Assets<LevelManifest>>,
+    game_resources: Res<GameResources>,
+    asset_server: Res<AssetServer>,
+    mut clock: ResMut<LevelClock>,
+    mut result: ResMut<ChallengeResult>,
+) {
+    *result = ChallengeResult::default();
+    clock.challenge_limit = None;
+    if !settings.timed {
+        return;
+    }
+    let thresholds = match current_thresholds(&levels, &manifests, &game_resources) {
+        Some(thresholds) => thresholds,
+        None => return,
+    };
+    clock.challenge_limit = Some(thresholds.bronze.time);
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                format_time(thresholds.bronze.time),
+                TextStyle {
+                    font: asset_server.load("FredokaOne-Regular.ttf"),
+                    font_size: 40.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(10.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Countdown { thresholds });
+}
+
+pub fn update_countdown(clock: Res<LevelClock>, mut countdowns: Query<(&mut Text, &Countdown)>) {
+    for (mut text, countdown) in countdowns.iter_mut() {
+        // Count down to the best medal that can still be reached in time
+        let next = countdown
+            .thresholds
+            .iter()
+            .into_iter()
+            .find(|(_, threshold)| clock.elapsed <= threshold.time);
+        text.sections[0].value = match next {
+            Some((medal, threshold)) => format!(
+                "{} {}",
+                medal.label(),
+                format_time(threshold.time - clock.elapsed)
+            ),
+            None => format_time(0.),
+        };
+    }
+}
+
+pub fn remove_countdown(mut commands: Commands, countdowns: Query<Entity, With<Countdown>>) {
+    for entity in countdowns.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn award_medal(
+    settings: Res<ChallengeSettings>,
+    levels: Res<Levels>,
+    manifests: Res<Assets<LevelManifest>>,
+    game_resources: Res<GameResources>,
+    clock: Res<LevelClock>,
+    mut result: ResMut<ChallengeResult>,
+    mut progress: ResMut<Progress>,
+) {
+    let herd_completed = game_resources
+        .outcome
+        .is_some_and(|outcome| outcome.herd_completed());
+    if !settings.timed || !herd_completed {
+        return;
+    }
+    let thresholds = match current_thresholds(&levels, &manifests, &game_resources) {
+        Some(thresholds) => thresholds,
+        None => return,
+    };
+    let level = game_resources.current_level.clone();
+    let animals = game_resources.delivered_animals_count + game_resources.herd_home_count;
+    result.medal = thresholds.medal(clock.elapsed, animals);
+    // Runs that don't earn a medal don't count towards the best time either
+    let medal = match result.medal {
+        Some(medal) => medal,
+        None => return,
+    };
+    if progress.medals.get(&level).is_none_or(|&best| medal > best) {
+        progress.medals.insert(level.clone(), medal);
+        result.new_record = true;
+    }
+    let best_time = progress.best_times.entry(level).or_insert(f32::MAX);
+    *best_time = best_time.min(clock.elapsed);
+    save::write("progress.ron", &*progress);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thresholds() -> MedalThresholds {
+        let threshold = |time, animals| MedalThreshold { time, animals };
+        MedalThresholds {
+            bronze: threshold(120., 5),
+            silver: threshold(90., 8),
+            gold: threshold(60., 10),
+        }
+    }
+
+    #[test]
+    fn best_medal_reached_on_both_time_and_animals() {
+        let thresholds = thresholds();
+        assert_eq!(thresholds.medal(60., 10), Some(Medal::Gold));
+        assert_eq!(thresholds.medal(30., 9), Some(Medal::Silver));
+        assert_eq!(thresholds.medal(61., 10), Some(Medal::Silver));
+        assert_eq!(thresholds.medal(100., 12), Some(Medal::Bronze));
+    }
+
+    #[test]
+    fn no_medal_when_too_slow_or_too_few() {
+        let thresholds = thresholds();
+        assert_eq!(thresholds.medal(121., 12), None);
+        assert_eq!(thresholds.medal(10., 4), None);
+    }
+}
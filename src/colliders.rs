@@ -0,0 +1,509 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use bevy::ecs::system::SystemParam;
+use bevy::gltf::GltfExtras;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy_rapier3d::prelude::*;
+use bevy_rapier3d::rapier::math::{Point, Vector};
+use bevy_rapier3d::rapier::prelude::SharedShape;
+use serde::{Deserialize, Serialize};
+
+use crate::physics::PhysicsOverrides;
+use crate::save;
+use crate::surface::Surface;
+use crate::GameResources;
+
+// Chosen per node in Blender with a "collider" custom property, eg. "convex_hull". Without one a
+// node gets a convex decomposition, the animals use CCD and that tunnels through a TriMesh.
+// Decomposition is slow, but the collider cache means it only runs once per mesh
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ColliderKind {
+    TriMesh,
+    ConvexHull,
+    ConvexDecomposition,
+    Heightfield,
+}
+
+impl ColliderKind {
+    pub fn from_extras(extras: Option<&GltfExtras>) -> ColliderKind {
+        ColliderKind::named(extras).unwrap_or(ColliderKind::ConvexDecomposition)
+    }
+
+    fn named(extras: Option<&GltfExtras>) -> Option<ColliderKind> {
+        let value = serde_json::from_str::<serde_json::Value>(&extras?.value).ok()?;
+        match value.get("collider")?.as_str()? {
+            "trimesh" => Some(ColliderKind::TriMesh),
+            "convex_hull" => Some(ColliderKind::ConvexHull),
+            "convex_decomposition" => Some(ColliderKind::ConvexDecomposition),
+            "heightfield" => Some(ColliderKind::Heightfield),
+            _ => None,
+        }
+    }
+}
+
+// Stand-in primitives modelled as unit shapes in Blender, sized by the node's scale
+pub fn proxy_collider(name: &str) -> Option<Collider> {
+    match name.split('.').nth(1)? {
+        "Box" => Some(Collider::cuboid(1., 1., 1.)),
+        "Ball" => Some(Collider::ball(1.)),
+        "Capsule" => Some(Collider::capsule_y(0.5, 0.5)),
+        "Cylinder" => Some(Collider::cylinder(1., 1.)),
+        _ => None,
+    }
+}
+
+pub fn is_proxy(name: &str) -> bool {
+    name.starts_with("Proxy.")
+}
+
+fn positions(mesh: &Mesh) -> Option<Vec<Vec3>> {
+    match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float32x3(positions) => {
+            Some(positions.iter().map(|&position| position.into()).collect())
+        }
+        _ => None,
+    }
+}
+
+// Expects a regular grid of vertices, like a subdivided and displaced plane
+fn heightfield(mesh: &Mesh) -> Option<Collider> {
+    let positions = positions(mesh)?;
+    let key = |value: f32| (value * 1000.).round() as i64;
+    let mut xs = positions.iter().map(|p| key(p.x)).collect::<Vec<_>>();
+    let mut zs = positions.iter().map(|p| key(p.z)).collect::<Vec<_>>();
+    xs.sort_unstable();
+    xs.dedup();
+    zs.sort_unstable();
+    zs.dedup();
+    let (num_cols, num_rows) = (xs.len(), zs.len());
+    if num_cols < 2 || num_rows < 2 || num_cols * num_rows != positions.len() {
+        return None;
+    }
+    let min = positions
+        .iter()
+        .fold(Vec3::splat(f32::MAX), |min, &p| min.min(p));
+    let max = positions
+        .iter()
+        .fold(Vec3::splat(f32::MIN), |max, &p| max.max(p));
+    // Rapier stores heights column-major, rows along Z and columns along X
+    let mut heights = vec![0.; num_rows * num_cols];
+    for position in positions.iter() {
+        let row = zs.binary_search(&key(position.z)).ok()?;
+        let col = xs.binary_search(&key(position.x)).ok()?;
+        heights[row + col * num_rows] = position.y;
+    }
+    let size = max - min;
+    let center = (min + max) / 2.;
+    Some(Collider::compound(vec![(
+        Vec3::new(center.x, 0., center.z),
+        Quat::IDENTITY,
+        Collider::heightfield(heights, num_rows, num_cols, Vector::new(size.x, 1., size.z)),
+    )]))
+}
+
+// Content hash, so a re-exported mesh never picks up a stale collider
+fn mesh_key(mesh: &Mesh, kind: ColliderKind) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    for position in positions(mesh).unwrap_or_default() {
+        position.to_array().map(f32::to_bits).hash(&mut hasher);
+    }
+    match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.hash(&mut hasher),
+        Some(Indices::U32(indices)) => indices.hash(&mut hasher),
+        None => {}
+    }
+    hasher.finish()
+}
+
+// What a collider was built from, so the cache can be written to disk and the collider rebuilt
+// on load without running VHACD or the heightfield fit again
+#[derive(Serialize, Deserialize)]
+enum ColliderShape {
+    TriMesh {
+        vertices: Vec<[f32; 3]>,
+        indices: Vec<[u32; 3]>,
+    },
+    ConvexHull {
+        points: Vec<[f32; 3]>,
+    },
+    // Heights are column-major, like Rapier keeps them
+    Heightfield {
+        heights: Vec<f32>,
+        num_rows: usize,
+        num_cols: usize,
+        scale: [f32; 3],
+    },
+    // Parts with their translation and rotation, eg. the pieces of a convex decomposition
+    Compound {
+        parts: Vec<([f32; 3], [f32; 4], ColliderShape)>,
+    },
+}
+
+impl ColliderShape {
+    fn from_collider(collider: &Collider) -> Option<ColliderShape> {
+        ColliderShape::from_shape(&collider.raw)
+    }
+
+    fn from_shape(shape: &SharedShape) -> Option<ColliderShape> {
+        let point = |point: &Point<f32>| [point.x, point.y, point.z];
+        if let Some(trimesh) = shape.as_trimesh() {
+            Some(ColliderShape::TriMesh {
+                vertices: trimesh.vertices().iter().map(point).collect(),
+                indices: trimesh.indices().to_vec(),
+            })
+        } else if let Some(convex) = shape.as_convex_polyhedron() {
+            Some(ColliderShape::ConvexHull {
+                points: convex.points().iter().map(point).collect(),
+            })
+        } else if let Some(heightfield) = shape.as_heightfield() {
+            let (heights, scale) = (heightfield.heights(), heightfield.scale());
+            Some(ColliderShape::Heightfield {
+                heights: heights.as_slice().to_vec(),
+                num_rows: heights.nrows(),
+                num_cols: heights.ncols(),
+                scale: [scale.x, scale.y, scale.z],
+            })
+        } else if let Some(compound) = shape.as_compound() {
+            let parts = compound
+                .shapes()
+                .iter()
+                .map(|(isometry, shape)| {
+                    let translation = isometry.translation.vector;
+                    let rotation = isometry.rotation.coords;
+                    Some((
+                        [translation.x, translation.y, translation.z],
+                        [rotation.x, rotation.y, rotation.z, rotation.w],
+                        ColliderShape::from_shape(shape)?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(ColliderShape::Compound { parts })
+        } else {
+            None
+        }
+    }
+
+    fn collider(&self) -> Option<Collider> {
+        match self {
+            ColliderShape::TriMesh { vertices, indices } => Some(Collider::trimesh(
+                vertices.iter().map(|&vertex| vertex.into()).collect(),
+                indices.clone(),
+            )),
+            ColliderShape::ConvexHull { points } => {
+                Collider::convex_hull(&points.iter().map(|&point| point.into()).collect::<Vec<_>>())
+            }
+            ColliderShape::Heightfield {
+                heights,
+                num_rows,
+                num_cols,
+                scale,
+            } => {
+                if heights.len() != num_rows * num_cols {
+                    return None;
+                }
+                Some(Collider::heightfield(
+                    heights.clone(),
+                    *num_rows,
+                    *num_cols,
+                    Vector::from(*scale),
+                ))
+            }
+            ColliderShape::Compound { parts } => Some(Collider::compound(
+                parts
+                    .iter()
+                    .map(|(translation, rotation, shape)| {
+                        Some((
+                            Vec3::from(*translation),
+                            Quat::from_array(*rotation),
+                            shape.collider()?,
+                        ))
+                    })
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+        }
+    }
+}
+
+pub struct ColliderCache {
+    // Also keep a copy in the save directory, not available on the web
+    pub persist: bool,
+    colliders: HashMap<u64, Collider>,
+    // Keys used by the current level, the only ones written back to disk
+    used: HashSet<u64>,
+    dirty: bool,
+}
+
+impl Default for ColliderCache {
+    fn default() -> Self {
+        ColliderCache {
+            persist: !cfg!(target_arch = "wasm32"),
+            colliders: HashMap::new(),
+            used: HashSet::new(),
+            dirty: false,
+        }
+    }
+}
+
+impl ColliderCache {
+    pub fn mesh_collider(&mut self, mesh: &Mesh, kind: ColliderKind) -> Option<Collider> {
+        let key = mesh_key(mesh, kind);
+        self.used.insert(key);
+        if let Some(collider) = self.colliders.get(&key) {
+            return Some(collider.clone());
+        }
+        let collider = mesh_collider(mesh, kind)?;
+        self.colliders.insert(key, collider.clone());
+        self.dirty = true;
+        Some(collider)
+    }
+}
+
+fn cache_name(level: &str) -> String {
+    format!("{}.colliders", level)
+}
+
+pub fn load_collider_cache(mut cache: ResMut<ColliderCache>, game_resources: Res<GameResources>) {
+    cache.used.clear();
+    if !cache.persist {
+        return;
+    }
+    let bytes = match save::read_bytes(&cache_name(&game_resources.current_level)) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+    match bincode::deserialize::<HashMap<u64, ColliderShape>>(&bytes) {
+        Ok(shapes) => cache.colliders.extend(
+            shapes
+                .into_iter()
+                .filter_map(|(key, shape)| Some((key, shape.collider()?))),
+        ),
+        Err(err) => println!("Ignoring collider cache: {}", err),
+    }
+}
+
+pub fn save_collider_cache(mut cache: ResMut<ColliderCache>, game_resources: Res<GameResources>) {
+    if !cache.dirty {
+        return;
+    }
+    cache.dirty = false;
+    if !cache.persist {
+        return;
+    }
+    let shapes = cache
+        .colliders
+        .iter()
+        .filter(|(key, _)| cache.used.contains(key))
+        .filter_map(|(&key, collider)| Some((key, ColliderShape::from_collider(collider)?)))
+        .collect::<HashMap<_, _>>();
+    let result = bincode::serialize(&shapes)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| save::write_bytes(&cache_name(&game_resources.current_level), &bytes));
+    if let Err(err) = result {
+        println!("Couldn't save collider cache: {}", err);
+    }
+}
+
+fn convex_hull(mesh: &Mesh) -> Option<Collider> {
+    Collider::convex_hull(&positions(mesh)?)
+}
+
+fn mesh_collider(mesh: &Mesh, kind: ColliderKind) -> Option<Collider> {
+    let collider = match kind {
+        ColliderKind::TriMesh => None,
+        ColliderKind::ConvexHull => convex_hull(mesh),
+        ColliderKind::ConvexDecomposition => Collider::from_bevy_mesh(
+            mesh,
+            &ComputedColliderShape::ConvexDecomposition(VHACDParameters::default()),
+        ),
+        ColliderKind::Heightfield => heightfield(mesh),
+    };
+    match collider {
+        Some(collider) => Some(collider),
+        None => {
+            if kind != ColliderKind::TriMesh {
+                println!(
+                    "Couldn't build {:?} collider, using a TriMesh instead",
+                    kind
+                );
+            }
+            Collider::from_bevy_mesh(mesh, &ComputedColliderShape::TriMesh)
+        }
+    }
+}
+
+// What level nodes need to get colliders for their render meshes
+#[derive(SystemParam)]
+pub struct LevelMeshes<'w, 's> {
+    meshes: Query<'w, 's, &'static Handle<Mesh>>,
+    mesh_assets: Res<'w, Assets<Mesh>>,
+    materials: Query<'w, 's, &'static Handle<StandardMaterial>>,
+    names: Query<'w, 's, &'static Name>,
+    children: Query<'w, 's, &'static Children>,
+    extras: Query<'w, 's, &'static GltfExtras>,
+    cache: ResMut<'w, ColliderCache>,
+}
+
+impl<'w, 's> LevelMeshes<'w, 's> {
+    // Every node below `node`, at any depth, that has render meshes of its own. Subtrees whose
+    // root `skip` picks by name are left out entirely, eg. animals that bring their own colliders
+    pub fn geometry_nodes(&self, node: Entity, skip: &impl Fn(&str) -> bool) -> Vec<Entity> {
+        let mut found = Vec::new();
+        self.find_geometry(node, skip, &mut found);
+        found
+    }
+
+    fn find_geometry(&self, node: Entity, skip: &impl Fn(&str) -> bool, found: &mut Vec<Entity>) {
+        if self.names.get(node).is_ok_and(|name| skip(name.as_str())) {
+            return;
+        }
+        let children = match self.children.get(node) {
+            Ok(children) => children,
+            Err(_) => return,
+        };
+        // Mesh primitives are spawned as children of their node, anything else is another node
+        let (primitives, nodes): (Vec<Entity>, Vec<Entity>) = children
+            .iter()
+            .partition(|&&child| self.meshes.get(child).is_ok());
+        if !primitives.is_empty() {
+            found.push(node);
+        }
+        for child in nodes {
+            self.find_geometry(child, skip, found);
+        }
+    }
+
+    // Each primitive gets a collider on its own entity, otherwise only the last one of a
+    // multi-material node would be solid. Errs with the node's name if one can't be built
+    pub fn build_colliders(
+        &mut self,
+        commands: &mut Commands,
+        node: Entity,
+        material_surfaces: &HashMap<Handle<StandardMaterial>, Surface>,
+    ) -> Result<(), String> {
+        let children = match self.children.get(node) {
+            Ok(children) => children,
+            Err(_) => return Ok(()),
+        };
+        // Hand-placed proxies replace the render mesh as the collision shape
+        let has_proxies = children.iter().any(|&child| {
+            self.names
+                .get(child)
+                .is_ok_and(|name| is_proxy(name.as_str()))
+        });
+        if has_proxies {
+            return Ok(());
+        }
+        let extras = self.extras.get(node).ok();
+        let kind = ColliderKind::from_extras(extras);
+        let overrides = PhysicsOverrides::from_extras(extras);
+        let tagged_surface = Surface::from_extras(extras);
+        for &child in children.iter() {
+            let mesh = match self.meshes.get(child) {
+                Ok(mesh) => mesh,
+                Err(_) => continue,
+            };
+            let collider = self
+                .mesh_assets
+                .get(mesh)
+                .and_then(|mesh| self.cache.mesh_collider(mesh, kind));
+            let collider = match collider {
+                Some(collider) => collider,
+                None => {
+                    let name = self.names.get(node).map_or("", |name| name.as_str());
+                    return Err(format!("couldn't build collision for {}", name));
+                }
+            };
+            let material_surface = self
+                .materials
+                .get(child)
+                .ok()
+                .and_then(|material| material_surfaces.get(material));
+            let mut child = commands.entity(child);
+            child.insert(collider);
+            overrides.apply_surface(&mut child);
+            if let Some(&surface) = tagged_surface.as_ref().or(material_surface) {
+                child.insert(surface);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::mesh::{Indices, PrimitiveTopology};
+
+    use super::*;
+
+    fn grid(positions: Vec<[f32; 3]>) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh
+    }
+
+    #[test]
+    fn heightfield_follows_a_regular_grid() {
+        // 3 columns along X from -2 to 2, 2 rows along Z from 0 to 4, height is x + z
+        let positions = [0., 4.]
+            .into_iter()
+            .flat_map(|z| [-2., 0., 2.].map(|x| [x, x + z, z]))
+            .collect::<Vec<_>>();
+        let collider = heightfield(&grid(positions)).unwrap();
+        let (isometry, shape) = &collider.raw.as_compound().unwrap().shapes()[0];
+        assert_eq!(isometry.translation.vector, Vector::new(0., 0., 2.));
+        let heightfield = shape.as_heightfield().unwrap();
+        assert_eq!(*heightfield.scale(), Vector::new(4., 1., 4.));
+        let heights = heightfield.heights();
+        assert_eq!((heights.nrows(), heights.ncols()), (2, 3));
+        assert_eq!(heights[(0, 0)], -2.);
+        assert_eq!(heights[(0, 2)], 2.);
+        assert_eq!(heights[(1, 0)], 2.);
+        assert_eq!(heights[(1, 2)], 6.);
+    }
+
+    #[test]
+    fn heightfield_rejects_meshes_that_arent_a_grid() {
+        assert!(heightfield(&grid(vec![[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]])).is_none());
+        assert!(heightfield(&grid(vec![[0., 0., 0.], [1., 0., 0.]])).is_none());
+    }
+
+    #[test]
+    fn nodes_without_a_collider_property_get_a_ccd_safe_shape() {
+        let extras = |value: &str| GltfExtras {
+            value: value.to_string(),
+        };
+        assert_eq!(
+            ColliderKind::from_extras(None),
+            ColliderKind::ConvexDecomposition
+        );
+        assert_eq!(
+            ColliderKind::from_extras(Some(&extras(r#"{"friction": 0.5}"#))),
+            ColliderKind::ConvexDecomposition
+        );
+        assert_eq!(
+            ColliderKind::from_extras(Some(&extras(r#"{"collider": "trimesh"}"#))),
+            ColliderKind::TriMesh
+        );
+    }
+
+    #[test]
+    fn flat_floors_decompose_into_convex_pieces() {
+        let mut floor = grid(vec![
+            [-5., 0., -5.],
+            [5., 0., -5.],
+            [5., 0., 5.],
+            [-5., 0., 5.],
+        ]);
+        floor.set_indices(Some(Indices::U32(vec![0, 2, 1, 0, 3, 2])));
+        let collider = mesh_collider(&floor, ColliderKind::ConvexDecomposition).unwrap();
+        let shapes = collider.raw.as_compound().unwrap().shapes();
+        assert!(!shapes.is_empty());
+        assert!(shapes
+            .iter()
+            .all(|(_, shape)| shape.as_convex_polyhedron().is_some()));
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::party::PartyZone;
+use crate::replay::{FrameInput, Replayer};
+use crate::simulation::{self, GameRng, Interpolated};
+use crate::species::Species;
+use crate::{
+    animal_physics, challenge, facing, gentle, ghost, outcome, parental, simulation_systems,
+    AnimalFell, AnimalRecruited, AppState, Collectable, GameResources, Player,
+};
+
+const TICKS: usize = 900;
+const SCRIPT_SEED: u64 = 7;
+const HERD_SIDE: i32 = 4;
+
+// Runs the game's own simulation stage headless, with a scripted herd played back through the
+// replay input, and returns every animal's transform as raw bits. Between ticks the animals are
+// drawn `alpha` of the way between poses, like a frame would, which mustn't change the run
+fn simulate(script: &[FrameInput], alpha: f32) -> Vec<[u32; 10]> {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(bevy::asset::AssetPlugin)
+        .add_plugin(bevy::input::InputPlugin)
+        .add_plugin(bevy::transform::TransformPlugin)
+        .add_plugin(bevy::hierarchy::HierarchyPlugin)
+        .add_plugin(bevy::scene::ScenePlugin)
+        .add_asset::<Mesh>()
+        .add_state(AppState::InGame)
+        .add_event::<AnimalRecruited>()
+        .add_event::<AnimalFell>()
+        .add_event::<gentle::Cheer>()
+        .insert_resource(Replayer::playback(script.to_vec()))
+        .insert_resource(GameRng(StdRng::seed_from_u64(SCRIPT_SEED)))
+        .init_resource::<FrameInput>()
+        .init_resource::<parental::Session>()
+        .init_resource::<facing::TurnRates>()
+        .init_resource::<gentle::GentleMode>()
+        .init_resource::<outcome::LevelClock>()
+        .init_resource::<challenge::ChallengeSettings>()
+        .init_resource::<ghost::GhostRecorder>()
+        .insert_resource(GameResources {
+            scene_entity: None,
+            ui_node: None,
+            delivered_animals_count: 0,
+            total_animals_count: 0,
+            herd_home_count: 0,
+            outcome: None,
+            current_level: "determinism".to_string(),
+        })
+        .add_startup_system(spawn_herd);
+    simulation::add_physics(&mut app);
+    // No run criteria, so the simulation ticks exactly once per update
+    app.add_stage_after(
+        CoreStage::Update,
+        simulation::Simulation,
+        simulation::schedule(simulation_systems()),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        (move |mut bodies: Query<(&Interpolated, &mut Transform)>| {
+            simulation::interpolate(alpha, &mut bodies)
+        })
+        .before(bevy::transform::TransformSystem::TransformPropagate),
+    );
+    for _ in 0..TICKS {
+        app.update();
+    }
+
+    let mut animals = app
+        .world
+        .query_filtered::<(Entity, &Interpolated), With<Collectable>>()
+        .iter(&app.world)
+        .map(|(entity, interpolated)| (entity.id(), transform_bits(interpolated.current())))
+        .collect::<Vec<_>>();
+    animals.sort_by_key(|&(id, _)| id);
+    animals.into_iter().map(|(_, bits)| bits).collect()
+}
+
+fn transform_bits(transform: &Transform) -> [u32; 10] {
+    let mut bits = [0; 10];
+    let values = transform
+        .translation
+        .to_array()
+        .into_iter()
+        .chain(transform.rotation.to_array())
+        .chain(transform.scale.to_array());
+    for (bit, value) in bits.iter_mut().zip(values) {
+        *bit = value.to_bits();
+    }
+    bits
+}
+
+fn spawn_herd(mut commands: Commands) {
+    commands
+        .spawn_bundle(TransformBundle::from(Transform::from_xyz(0., -0.1, 0.)))
+        .insert(Collider::cuboid(100., 0.1, 100.));
+    // A couple of obstacles so the herd has something to pile up against
+    for x in [-20., 20.] {
+        commands
+            .spawn_bundle(TransformBundle::from(Transform::from_xyz(x, 2., 5.)))
+            .insert(Collider::cuboid(2., 2., 10.));
+    }
+    let party_position = Vec3::new(0., 1., -25.);
+    let transform = Transform::from_translation(party_position);
+    commands
+        .spawn_bundle(TransformBundle::from(transform))
+        .insert(Collider::cuboid(1., 1., 1.))
+        .insert(PartyZone::new(party_position, None))
+        .insert(RigidBody::KinematicPositionBased)
+        .insert(Interpolated::new(&transform));
+    // A herd in the middle, and a ring of strays for it to pick up on the way
+    let herd = (0..HERD_SIDE).flat_map(|row| {
+        (0..HERD_SIDE).map(move |column| {
+            let position = Vec3::new(column as f32 * 4.5 - 7., 2.5, row as f32 * 4.5 - 7.);
+            (position, true)
+        })
+    });
+    let strays = (0..8).map(|index| {
+        let angle = index as f32 * std::f32::consts::TAU / 8.;
+        (Vec3::new(angle.cos() * 30., 2.5, angle.sin() * 30.), false)
+    });
+    for (index, (position, in_herd)) in herd.chain(strays).enumerate() {
+        let transform = Transform::from_translation(position);
+        let mut animal = commands.spawn_bundle(TransformBundle::from(transform));
+        animal
+            .insert(Name::new(format!("Puppy.{:03}", index)))
+            .insert_bundle(animal_physics())
+            .insert(Species::Puppy)
+            .insert(facing::Facing::new(&transform))
+            .insert(Interpolated::new(&transform))
+            .insert(Collectable {});
+        if in_herd {
+            animal.insert(Player {
+                spawn_position: position,
+            });
+        }
+    }
+}
+
+// Wanders the herd around, holding each direction for a second or so
+fn script() -> Vec<FrameInput> {
+    let mut rng = StdRng::seed_from_u64(SCRIPT_SEED);
+    let mut frames = Vec::new();
+    while frames.len() < TICKS {
+        let angle = rng.gen_range(0. ..std::f32::consts::TAU);
+        let strength = rng.gen_range(0. ..=1.);
+        let frame = FrameInput {
+            movement: [angle.cos() * strength, angle.sin() * strength],
+            cheer: rng.gen_bool(0.3),
+            speed: 1.,
+        };
+        frames.push(frame);
+        frames.extend(std::iter::repeat_n(
+            FrameInput {
+                cheer: false,
+                ..frame
+            },
+            59,
+        ));
+    }
+    frames
+}
+
+#[test]
+fn same_input_gives_the_same_run() {
+    let script = script();
+    let first = simulate(&script, 0.25);
+    let second = simulate(&script, 0.75);
+    assert!(!first.is_empty());
+    assert_eq!(first.len(), second.len());
+    let mismatches = first
+        .iter()
+        .zip(second.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    assert_eq!(
+        mismatches,
+        0,
+        "{} of {} animals differ after {} ticks",
+        mismatches,
+        first.len(),
+        TICKS
+    );
+}
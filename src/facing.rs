@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::simulation::SimulationTime;
+use crate::species::Species;
+
+const DEFAULT_TURN_RATE: f32 = 10.;
+const MIN_FACING_SPEED: f32 = 1.;
+const MAX_TILT: f32 = 0.25;
+const TILT_PER_TURN_RATE: f32 = 0.04;
+const TILT_SMOOTHING: f32 = 8.;
+
+// Radians per second each species can turn towards its heading
+pub struct TurnRates(pub HashMap<Species, f32>);
+
+impl Default for TurnRates {
+    fn default() -> Self {
+        TurnRates(HashMap::from([
+            (Species::Puppy, 10.),
+            (Species::Kitty, 14.),
+            (Species::Bunny, 8.),
+        ]))
+    }
+}
+
+impl TurnRates {
+    pub fn get(&self, species: &Species) -> f32 {
+        self.0.get(species).copied().unwrap_or(DEFAULT_TURN_RATE)
+    }
+}
+
+#[derive(Component)]
+pub struct Facing {
+    // Set by input each frame, when empty the animal faces along its velocity instead
+    pub desired: Option<Vec2>,
+    heading: Quat,
+    tilt: f32,
+}
+
+impl Facing {
+    pub fn new(transform: &Transform) -> Facing {
+        let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        Facing {
+            desired: None,
+            heading: Quat::from_rotation_y(yaw),
+            tilt: 0.,
+        }
+    }
+
+    // Picks up a heading and lean that were saved with heading_and_tilt
+    pub fn restored(heading: Quat, tilt: f32) -> Facing {
+        Facing {
+            desired: None,
+            heading,
+            tilt,
+        }
+    }
+
+    pub fn heading_and_tilt(&self) -> (Quat, f32) {
+        (self.heading, self.tilt)
+    }
+}
+
+// Models face +Z, so a heading along +X is a quarter turn
+fn heading_towards(direction: Vec2) -> Quat {
+    Quat::from_rotation_y(FRAC_PI_2 + direction.angle_between(Vec2::X))
+}
+
+pub fn turn_animals(
+    time: Res<SimulationTime>,
+    turn_rates: Res<TurnRates>,
+    mut animals: Query<(&mut Facing, &mut Transform, &Velocity, &Species)>,
+) {
+    let delta = time.delta_seconds();
+    if delta <= 0. {
+        return;
+    }
+    for (mut facing, mut transform, velocity, species) in animals.iter_mut() {
+        let horizontal_velocity = Vec2::new(velocity.linvel.x, velocity.linvel.z);
+        let direction = match facing.desired.take() {
+            Some(direction) => Some(direction),
+            None if horizontal_velocity.length() > MIN_FACING_SPEED => Some(horizontal_velocity),
+            None => None,
+        };
+        let previous_heading = facing.heading;
+        if let Some(direction) = direction {
+            let target = heading_towards(direction);
+            let angle = facing.heading.angle_between(target);
+            let t = if angle > f32::EPSILON {
+                (turn_rates.get(species) * delta / angle).min(1.)
+            } else {
+                1.
+            };
+            facing.heading = facing.heading.slerp(target, t);
+        }
+
+        // Lean into the turn proportionally to how fast we're turning
+        let turned = (previous_heading * Vec3::Z)
+            .cross(facing.heading * Vec3::Z)
+            .y
+            .clamp(-1., 1.)
+            .asin();
+        let target_tilt = (-turned / delta * TILT_PER_TURN_RATE).clamp(-MAX_TILT, MAX_TILT);
+        facing.tilt += (target_tilt - facing.tilt) * (TILT_SMOOTHING * delta).min(1.);
+        transform.rotation = facing.heading * Quat::from_rotation_z(facing.tilt);
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::outcome::{self, CompleteReason, LevelOutcome};
+use crate::replay::FrameInput;
+use crate::{AnimalFell, AppState, GameResources, Player};
+
+const STRAGGLER_DISTANCE: f32 = 20.;
+const REGROUP_OFFSET: f32 = 3.;
+const HOP_SPEED: f32 = 12.;
+const PARENT_COMBO_SECONDS: f32 = 3.;
+
+// No fail states for the very young: nobody leaves the herd and the game only ends when a
+// parent holds the hidden combo
+pub struct GentleMode {
+    pub enabled: bool,
+}
+
+impl Default for GentleMode {
+    fn default() -> Self {
+        GentleMode {
+            enabled: std::env::args().any(|arg| arg == "--gentle"),
+        }
+    }
+}
+
+pub struct Cheer {
+    pub position: Vec3,
+}
+
+pub fn herd_center(positions: impl Iterator<Item = Vec3>) -> Option<Vec3> {
+    let (sum, count) = positions.fold((Vec3::ZERO, 0), |(sum, count), position| {
+        (sum + position, count + 1)
+    });
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f32)
+    }
+}
+
+// A spot next to the herd, spread around so regrouped animals don't stack up. The angle comes
+// from the animal's name, entity ids differ from one run to the next and replays have to agree
+pub fn beside_herd(center: Vec3, name: &Name) -> Vec3 {
+    let hash = name.as_str().bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as u32)
+    });
+    let angle = (hash % 1024) as f32 * 2.4;
+    center + Quat::from_rotation_y(angle) * Vec3::X * REGROUP_OFFSET + Vec3::Y * 2.
+}
+
+pub fn toggle_gentle_mode(
+    mut gentle_mode: ResMut<GentleMode>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    let toggled = keys.just_pressed(KeyCode::G)
+        || buttons
+            .get_just_pressed()
+            .any(|button| button.button_type == GamepadButtonType::Select);
+    if toggled {
+        gentle_mode.enabled = !gentle_mode.enabled;
+        println!("Gentle mode: {}", gentle_mode.enabled);
+    }
+}
+
+pub fn regroup_stragglers(
+    gentle_mode: Res<GentleMode>,
+    mut fell: EventWriter<AnimalFell>,
+    mut players: Query<(&Name, &mut Transform, &mut Velocity), With<Player>>,
+) {
+    if !gentle_mode.enabled {
+        return;
+    }
+    let center = match herd_center(
+        players
+            .iter()
+            .map(|(_, transform, _)| transform.translation),
+    ) {
+        Some(center) => center,
+        None => return,
+    };
+    for (name, mut transform, mut velocity) in players.iter_mut() {
+        if transform.translation.distance(center) > STRAGGLER_DISTANCE {
+            let position = beside_herd(center, name);
+            fell.send(AnimalFell {
+                respawn_position: position,
+            });
+            transform.translation = position;
+            velocity.linvel = Vec3::ZERO;
+        }
+    }
+}
+
+pub fn cheer_on_any_button(
+    gentle_mode: Res<GentleMode>,
+    input: Res<FrameInput>,
+    mut cheers: EventWriter<Cheer>,
+    mut players: Query<(&GlobalTransform, &mut Velocity), With<Player>>,
+) {
+    if !gentle_mode.enabled {
+        return;
+    }
+    if !input.cheer {
+        return;
+    }
+    for (transform, mut velocity) in players.iter_mut() {
+        velocity.linvel.y = HOP_SPEED;
+        cheers.send(Cheer {
+            position: transform.translation(),
+        });
+    }
+}
+
+// Escape + Backspace, or Select + Start + both triggers, held for a few seconds
+#[allow(clippy::too_many_arguments)]
+pub fn parent_combo(
+    time: Res<Time>,
+    gentle_mode: Res<GentleMode>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut held: Local<f32>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_resources: ResMut<GameResources>,
+) {
+    if !gentle_mode.enabled {
+        return;
+    }
+    let keys_held = keys.pressed(KeyCode::Escape) && keys.pressed(KeyCode::Back);
+    let buttons_held = gamepads.iter().any(|&gamepad| {
+        [
+            GamepadButtonType::Select,
+            GamepadButtonType::Start,
+            GamepadButtonType::LeftTrigger2,
+            GamepadButtonType::RightTrigger2,
+        ]
+        .into_iter()
+        .all(|button_type| buttons.pressed(GamepadButton::new(gamepad, button_type)))
+    });
+    if !keys_held && !buttons_held {
+        *held = 0.;
+        return;
+    }
+    *held += time.delta_seconds();
+    if *held > PARENT_COMBO_SECONDS {
+        *held = 0.;
+        outcome::finish_level(
+            &mut app_state,
+            &mut game_resources,
+            LevelOutcome::Complete(CompleteReason::EndedByParent),
+        );
+    }
+}
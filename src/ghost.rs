@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::ChallengeSettings;
+use crate::outcome::LevelClock;
+use crate::resume::PendingRestore;
+use crate::save::{self, Progress};
+use crate::simulation::TICK_SECONDS;
+use crate::{GameResources, Player};
+
+const GHOST_VERSION: u32 = 1;
+// Ten samples a second is plenty once they're blended
+const SAMPLE_TICKS: u32 = 6;
+const GHOST_RADIUS: f32 = 2.;
+
+// The best run is stored as where each herd member was rather than the input that moved it,
+// replaying input would need a second physics world for the ghosts to bump around in
+#[derive(Serialize, Deserialize)]
+struct GhostFile {
+    version: u32,
+    level: String,
+    time: f32,
+    // Per sample, each herd member's slot and position
+    samples: Vec<Vec<(u16, [f32; 3])>>,
+}
+
+// Saved with the parental settings, --no-ghost only turns ghosts off for one launch
+#[derive(Serialize, Deserialize)]
+pub struct GhostSettings {
+    pub enabled: bool,
+}
+
+impl Default for GhostSettings {
+    fn default() -> Self {
+        GhostSettings { enabled: true }
+    }
+}
+
+pub fn load_ghost_settings(mut commands: Commands) {
+    commands.insert_resource(save::read::<GhostSettings>("ghost.ron").unwrap_or_default());
+}
+
+#[derive(Default)]
+pub struct GhostRecorder {
+    ticks: u32,
+    // Herd members keep the same slot for the whole run, so ghosts don't swap places when
+    // one of them leaves the herd
+    slots: HashMap<Entity, u16>,
+    samples: Vec<Vec<(u16, [f32; 3])>>,
+    // A continued run starts part way through the level, so its samples wouldn't line up with
+    // the clock
+    resumed: bool,
+}
+
+// The personal best being raced in the current run
+pub struct GhostRun {
+    samples: Vec<Vec<(u16, [f32; 3])>>,
+}
+
+// Purely visual, no collider or rigid body, and not a Player or Collectable so nothing in the
+// gameplay picks it up
+#[derive(Component)]
+pub struct Ghost {
+    slot: u16,
+}
+
+fn ghost_name(level: &str) -> String {
+    format!("ghosts/{}.ghost", level)
+}
+
+fn read_ghost(level: &str) -> Option<GhostFile> {
+    let bytes = save::read_bytes(&ghost_name(level))?;
+    match bincode::deserialize::<GhostFile>(&bytes) {
+        Ok(file) if file.version == GHOST_VERSION && file.level == level => Some(file),
+        Ok(_) => None,
+        Err(err) => {
+            println!("Ignoring broken ghost for {}: {}", level, err);
+            None
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_ghost(
+    mut commands: Commands,
+    settings: Res<ChallengeSettings>,
+    ghost_settings: Res<GhostSettings>,
+    game_resources: Res<GameResources>,
+    resuming: Option<Res<PendingRestore>>,
+    mut recorder: ResMut<GhostRecorder>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    *recorder = GhostRecorder {
+        resumed: resuming.is_some(),
+        ..default()
+    };
+    commands.remove_resource::<GhostRun>();
+    let launched_without = std::env::args().any(|arg| arg == "--no-ghost");
+    if !settings.timed || !ghost_settings.enabled || launched_without {
+        return;
+    }
+    let file = match read_ghost(&game_resources.current_level) {
+        Some(file) => file,
+        None => return,
+    };
+    println!("Racing a ghost of {:.1}s", file.time);
+    let slot_count = file
+        .samples
+        .iter()
+        .flatten()
+        .map(|&(slot, _)| slot + 1)
+        .max()
+        .unwrap_or(0);
+    let mesh = meshes.add(Mesh::from(shape::UVSphere {
+        radius: GHOST_RADIUS,
+        ..default()
+    }));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::rgba(0.8, 0.9, 1., 0.35),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    for slot in 0..slot_count {
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(NotShadowCaster)
+            .insert(Ghost { slot });
+    }
+    commands.insert_resource(GhostRun {
+        samples: file.samples,
+    });
+}
+
+// Runs on the simulation tick so samples line up with the level clock
+pub fn record_ghost(
+    mut recorder: ResMut<GhostRecorder>,
+    players: Query<(Entity, &Transform), With<Player>>,
+) {
+    if recorder.resumed {
+        return;
+    }
+    recorder.ticks += 1;
+    if !(recorder.ticks - 1).is_multiple_of(SAMPLE_TICKS) {
+        return;
+    }
+    let mut sample = Vec::new();
+    for (entity, transform) in players.iter() {
+        let next_slot = recorder.slots.len() as u16;
+        let slot = *recorder.slots.entry(entity).or_insert(next_slot);
+        sample.push((slot, transform.translation.to_array()));
+    }
+    recorder.samples.push(sample);
+}
+
+pub fn move_ghosts(
+    clock: Res<LevelClock>,
+    run: Option<Res<GhostRun>>,
+    mut ghosts: Query<(&Ghost, &mut Transform, &mut Visibility)>,
+) {
+    let run = match run {
+        Some(run) => run,
+        None => return,
+    };
+    let position = clock.elapsed / (TICK_SECONDS as f32 * SAMPLE_TICKS as f32);
+    let index = position.floor() as usize;
+    let blend = position.fract();
+    let find = |sample: Option<&Vec<(u16, [f32; 3])>>, slot: u16| {
+        sample?
+            .iter()
+            .find(|&&(other, _)| other == slot)
+            .map(|&(_, position)| Vec3::from(position))
+    };
+    for (ghost, mut transform, mut visibility) in ghosts.iter_mut() {
+        let from = find(run.samples.get(index), ghost.slot);
+        let to = find(run.samples.get(index + 1), ghost.slot);
+        let position = match (from, to) {
+            (Some(from), Some(to)) => Some(from.lerp(to, blend)),
+            (Some(from), None) => Some(from),
+            _ => None,
+        };
+        visibility.is_visible = position.is_some();
+        if let Some(position) = position {
+            transform.translation = position;
+        }
+    }
+}
+
+pub fn remove_ghosts(mut commands: Commands, ghosts: Query<Entity, With<Ghost>>) {
+    for entity in ghosts.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// After the medal is awarded, so the best time already includes this run
+pub fn save_ghost(
+    settings: Res<ChallengeSettings>,
+    game_resources: Res<GameResources>,
+    clock: Res<LevelClock>,
+    progress: Res<Progress>,
+    mut recorder: ResMut<GhostRecorder>,
+) {
+    // Only whole runs where the herd finished the level, not ones a grown-up or bedtime ended
+    let herd_completed = game_resources
+        .outcome
+        .is_some_and(|outcome| outcome.herd_completed());
+    if !settings.timed || recorder.resumed || !herd_completed {
+        return;
+    }
+    let level = game_resources.current_level.clone();
+    if progress.best_times.get(&level) != Some(&clock.elapsed) {
+        return;
+    }
+    let file = GhostFile {
+        version: GHOST_VERSION,
+        level,
+        time: clock.elapsed,
+        samples: std::mem::take(&mut recorder.samples),
+    };
+    let name = ghost_name(&file.level);
+    let result = bincode::serialize(&file)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| save::write_bytes(&name, &bytes));
+    match result {
+        Ok(_) => println!("Saved a new best ghost to {}", name),
+        Err(err) => println!("Couldn't save ghost {}: {}", name, err),
+    }
+}
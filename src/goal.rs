@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use bevy::gltf::GltfExtras;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::gentle::GentleMode;
+use crate::outcome::{self, CompleteReason, LevelOutcome};
+use crate::simulation::SimulationTime;
+use crate::{AppState, GameResources, Player};
+
+// Completion rules are set on the Goal in Blender with custom properties, eg.
+// {"all_herd": false, "min_delivered": 5, "hold_seconds": 2, "time_limit": 120}
+#[derive(Component)]
+pub struct GoalZone {
+    pub time_limit: Option<f32>,
+    all_herd: bool,
+    min_delivered: i32,
+    hold: Timer,
+    inside: HashSet<Entity>,
+}
+
+impl GoalZone {
+    pub fn new(extras: Option<&GltfExtras>) -> GoalZone {
+        let value = extras
+            .and_then(|extras| serde_json::from_str::<serde_json::Value>(&extras.value).ok())
+            .unwrap_or_default();
+        GoalZone {
+            time_limit: value
+                .get("time_limit")
+                .and_then(|time_limit| time_limit.as_f64())
+                .map(|time_limit| time_limit as f32),
+            all_herd: value
+                .get("all_herd")
+                .and_then(|all_herd| all_herd.as_bool())
+                .unwrap_or(true),
+            min_delivered: value
+                .get("min_delivered")
+                .and_then(|min_delivered| min_delivered.as_i64())
+                .unwrap_or(0) as i32,
+            hold: Timer::from_seconds(
+                value
+                    .get("hold_seconds")
+                    .and_then(|hold_seconds| hold_seconds.as_f64())
+                    .unwrap_or(0.) as f32,
+                false,
+            ),
+            inside: HashSet::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.hold.reset();
+        self.inside.clear();
+    }
+}
+
+// Goal colliders live on the goal's mesh children
+fn goal_of(
+    entity: Entity,
+    parents: &Query<&Parent>,
+    goals: &Query<&mut GoalZone>,
+) -> Option<Entity> {
+    if goals.contains(entity) {
+        return Some(entity);
+    }
+    parents
+        .get(entity)
+        .ok()
+        .map(|parent| parent.get())
+        .filter(|&parent| goals.contains(parent))
+}
+
+pub fn track_goal(
+    mut collisions: EventReader<CollisionEvent>,
+    parents: Query<&Parent>,
+    mut goals: Query<&mut GoalZone>,
+) {
+    for collision in collisions.iter() {
+        let (a, b, entered) = match *collision {
+            CollisionEvent::Started(a, b, _) => (a, b, true),
+            CollisionEvent::Stopped(a, b, _) => (a, b, false),
+        };
+        let (goal, entity) = match (goal_of(a, &parents, &goals), goal_of(b, &parents, &goals)) {
+            (Some(goal), None) => (goal, b),
+            (None, Some(goal)) => (goal, a),
+            _ => continue,
+        };
+        let mut goal = goals.get_mut(goal).unwrap();
+        if entered {
+            goal.inside.insert(entity);
+        } else {
+            goal.inside.remove(&entity);
+        }
+    }
+}
+
+pub fn check_goal(
+    time: Res<SimulationTime>,
+    gentle_mode: Res<GentleMode>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_resources: ResMut<GameResources>,
+    players: Query<Entity, With<Player>>,
+    mut goals: Query<&mut GoalZone>,
+) {
+    if app_state.current() != &AppState::InGame || gentle_mode.enabled {
+        return;
+    }
+    let herd_size = players.iter().count();
+    for mut goal in goals.iter_mut() {
+        let herd_inside = players
+            .iter()
+            .filter(|entity| goal.inside.contains(entity))
+            .count();
+        let ready = herd_inside > 0
+            && (!goal.all_herd || herd_inside == herd_size)
+            && game_resources.delivered_animals_count >= goal.min_delivered;
+        if !ready {
+            goal.hold.reset();
+            continue;
+        }
+        goal.hold.tick(time.delta());
+        if goal.hold.finished() {
+            game_resources.herd_home_count = herd_inside as i32;
+            outcome::finish_level(
+                &mut app_state,
+                &mut game_resources,
+                LevelOutcome::Complete(CompleteReason::ReachedGoal),
+            );
+            return;
+        }
+    }
+}
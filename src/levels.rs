@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+use crate::challenge::MedalThresholds;
+
+#[derive(Deserialize, Clone)]
+pub struct LevelInfo {
+    // Also the glTF file name in assets
+    pub name: String,
+    pub title: String,
+    #[serde(default)]
+    pub medals: Option<MedalThresholds>,
+}
+
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "2d6c3b1e-8f4a-4a7e-b1d2-5c9e0f3a7b64"]
+pub struct LevelManifest {
+    pub levels: Vec<LevelInfo>,
+}
+
+pub struct Levels {
+    manifest: Handle<LevelManifest>,
+}
+
+impl Levels {
+    pub fn get<'a>(
+        &self,
+        manifests: &'a Assets<LevelManifest>,
+        name: &str,
+    ) -> Option<&'a LevelInfo> {
+        manifests
+            .get(&self.manifest)?
+            .levels
+            .iter()
+            .find(|level| level.name == name)
+    }
+
+    // The one after it in the manifest, none after the last level
+    pub fn next<'a>(
+        &self,
+        manifests: &'a Assets<LevelManifest>,
+        name: &str,
+    ) -> Option<&'a LevelInfo> {
+        let all = self.all(manifests);
+        let index = all.iter().position(|level| level.name == name)?;
+        all.get(index + 1)
+    }
+
+    // In manifest order, empty until the manifest has loaded
+    pub fn all<'a>(&self, manifests: &'a Assets<LevelManifest>) -> &'a [LevelInfo] {
+        manifests
+            .get(&self.manifest)
+            .map(|manifest| manifest.levels.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+pub fn load_levels(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Levels {
+        manifest: asset_server.load("manifest.levels.ron"),
+    });
+}
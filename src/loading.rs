@@ -0,0 +1,302 @@
+use bevy::asset::LoadState;
+use bevy::gltf::Gltf;
+use bevy::prelude::*;
+use bevy::scene::{SceneInstance, SceneSpawner};
+
+use crate::resume::PendingRestore;
+use crate::{restart, AppState, GameResources};
+
+// Sent once the level scene is spawned and every mesh in it is loaded, with the scene's
+// entities so connecting only looks at those
+pub struct LevelReady {
+    pub scene: Entity,
+    pub entities: Vec<Entity>,
+}
+
+// Why the level couldn't be played, shown on the error screen
+pub struct LoadError(pub String);
+
+pub fn fail(commands: &mut Commands, app_state: &mut State<AppState>, reason: String) {
+    println!("Level failed to load: {}", reason);
+    // Another state change already queued this frame wins, eg. leaving for the main menu
+    if let Err(err) = app_state.set(AppState::LoadFailed) {
+        println!("Couldn't show the load error: {:?}", err);
+        return;
+    }
+    commands.insert_resource(LoadError(reason));
+}
+
+pub fn error_message(load_error: Option<&LoadError>) -> String {
+    format!(
+        "Oh no, the level couldn't be loaded :(\n{}\nPress any key to go back",
+        load_error.map_or("", |load_error| load_error.0.as_str())
+    )
+}
+
+// Any key or button clears away what there is of the level and goes back to the main menu
+pub fn leave_load_error(
+    mut commands: Commands,
+    mut game_resources: ResMut<GameResources>,
+    mut app_state: ResMut<State<AppState>>,
+    buttons: Res<Input<GamepadButton>>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if buttons.get_just_pressed().count() == 0 && keys.get_just_pressed().count() == 0 {
+        return;
+    }
+    // Another change already queued this frame wins, eg. the settings opening on F1
+    if let Err(err) = app_state.set(AppState::MainMenu) {
+        println!("Couldn't go back to the main menu: {:?}", err);
+        return;
+    }
+    restart::leave_level(&mut commands, &mut game_resources);
+    commands.remove_resource::<LoadError>();
+    // A save that couldn't be continued isn't carried over to whatever is played next
+    commands.remove_resource::<PendingRestore>();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn watch_level_load(
+    mut commands: Commands,
+    // The scene entity already reported on, ready or not
+    mut reported: Local<Option<Entity>>,
+    game_resources: Res<GameResources>,
+    asset_server: Res<AssetServer>,
+    scene_spawner: Res<SceneSpawner>,
+    scenes: Query<(&Handle<Scene>, Option<&SceneInstance>)>,
+    meshes: Query<&Handle<Mesh>>,
+    mesh_assets: Res<Assets<Mesh>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut level_ready: EventWriter<LevelReady>,
+) {
+    let scene = match game_resources.scene_entity {
+        Some(scene) if *reported != Some(scene) => scene,
+        _ => return,
+    };
+    let (scene_handle, instance) = match scenes.get(scene) {
+        Ok(scene) => scene,
+        Err(_) => return,
+    };
+    if asset_server.get_load_state(scene_handle) == LoadState::Failed {
+        *reported = Some(scene);
+        let reason = format!("{}.gltf is missing or broken", game_resources.current_level);
+        fail(&mut commands, &mut app_state, reason);
+        return;
+    }
+    let instance = match instance {
+        Some(instance) if scene_spawner.instance_is_ready(**instance) => **instance,
+        _ => return,
+    };
+    let entities = match scene_spawner.iter_instance_entities(instance) {
+        Some(entities) => entities.collect::<Vec<_>>(),
+        None => return,
+    };
+    for mesh in meshes.iter_many(&entities) {
+        if asset_server.get_load_state(mesh) == LoadState::Failed {
+            *reported = Some(scene);
+            let reason = format!("a mesh in {} didn't load", game_resources.current_level);
+            fail(&mut commands, &mut app_state, reason);
+            return;
+        }
+        if mesh_assets.get(mesh).is_none() {
+            return;
+        }
+    }
+    *reported = Some(scene);
+    level_ready.send(LevelReady { scene, entities });
+}
+
+const MIN_LOADING_SECONDS: f32 = 1.5;
+const BAR_WIDTH: f32 = 400.;
+
+#[derive(Default)]
+pub struct LevelLoading {
+    // Set by connect_from_scene once the goal is found, a level without one fails to load
+    pub connected: bool,
+    gltf: Handle<Gltf>,
+    elapsed: f32,
+    // Eases towards the real progress so the bar slides rather than jumps
+    shown: f32,
+}
+
+impl LevelLoading {
+    pub fn gltf(&self) -> &Handle<Gltf> {
+        &self.gltf
+    }
+}
+
+#[derive(Component)]
+pub struct LoadingScreen;
+
+#[derive(Component)]
+pub struct LoadingBar;
+
+#[derive(Component)]
+pub struct LoadingHop;
+
+pub fn start_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game_resources: ResMut<GameResources>,
+    mut level_loading: ResMut<LevelLoading>,
+) {
+    *level_loading = LevelLoading {
+        gltf: asset_server.load(&format!("{}.gltf", game_resources.current_level)),
+        ..default()
+    };
+    if let Some(entity) = game_resources.ui_node.take() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgb(0.1, 0.15, 0.1).into(),
+            ..default()
+        })
+        .insert(LoadingScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(
+                TextBundle::from_section(
+                    "Rounding up the herd...",
+                    TextStyle {
+                        font: asset_server.load("FredokaOne-Regular.ttf"),
+                        font_size: 30.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(20.0)),
+                    ..default()
+                }),
+            );
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(BAR_WIDTH), Val::Px(24.0)),
+                        ..default()
+                    },
+                    color: Color::rgba(1., 1., 1., 0.2).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(0.), Val::Percent(100.0)),
+                                ..default()
+                            },
+                            color: Color::rgb(1., 0.8, 0.3).into(),
+                            ..default()
+                        })
+                        .insert(LoadingBar);
+                    // Hops along the end of the bar while it fills
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(20.0), Val::Px(20.0)),
+                                position_type: PositionType::Absolute,
+                                ..default()
+                            },
+                            color: Color::WHITE.into(),
+                            ..default()
+                        })
+                        .insert(LoadingHop);
+                });
+        });
+}
+
+// Loading the glTF file is the first 40%, its textures the next 40% and connecting the level
+// the rest. Failed textures count as done, the level is still playable without them
+fn progress(
+    asset_server: &AssetServer,
+    level_loading: &LevelLoading,
+    gltfs: &Assets<Gltf>,
+    materials: &Assets<StandardMaterial>,
+) -> (f32, bool) {
+    let gltf = match gltfs.get(&level_loading.gltf) {
+        Some(gltf) => gltf,
+        None => return (0., false),
+    };
+    let textures = gltf
+        .materials
+        .iter()
+        .filter_map(|material| materials.get(material))
+        .flat_map(|material| {
+            [
+                material.base_color_texture.clone(),
+                material.emissive_texture.clone(),
+                material.metallic_roughness_texture.clone(),
+                material.normal_map_texture.clone(),
+                material.occlusion_texture.clone(),
+            ]
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    let loaded = textures
+        .iter()
+        .filter(|texture| {
+            matches!(
+                asset_server.get_load_state(*texture),
+                LoadState::Loaded | LoadState::Failed
+            )
+        })
+        .count();
+    let texture_progress = if textures.is_empty() {
+        1.
+    } else {
+        loaded as f32 / textures.len() as f32
+    };
+    let connected_progress = if level_loading.connected { 0.2 } else { 0. };
+    (
+        0.4 + 0.4 * texture_progress + connected_progress,
+        level_loading.connected && loaded == textures.len(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_loading(
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    gltfs: Res<Assets<Gltf>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut level_loading: ResMut<LevelLoading>,
+    mut app_state: ResMut<State<AppState>>,
+    mut bar: Query<&mut Style, (With<LoadingBar>, Without<LoadingHop>)>,
+    mut hop: Query<&mut Style, With<LoadingHop>>,
+) {
+    let (target, done) = progress(&asset_server, &level_loading, &gltfs, &materials);
+    level_loading.elapsed += time.delta_seconds();
+    // Never faster than the minimum display time, so the bar always gets to fill up
+    let target = target.min(level_loading.elapsed / MIN_LOADING_SECONDS);
+    level_loading.shown += (target - level_loading.shown) * (time.delta_seconds() * 8.).min(1.);
+    let width = BAR_WIDTH * level_loading.shown;
+    for mut style in bar.iter_mut() {
+        style.size.width = Val::Px(width);
+    }
+    for mut style in hop.iter_mut() {
+        let height = (time.seconds_since_startup() * 8.).sin().abs() as f32 * 20.;
+        style.position = UiRect {
+            left: Val::Px(width - 10.),
+            bottom: Val::Px(26. + height),
+            ..default()
+        };
+    }
+    if done && level_loading.elapsed >= MIN_LOADING_SECONDS {
+        // Tried again next frame if something else got its change in first
+        if let Err(err) = app_state.set(AppState::InGame) {
+            println!("Couldn't start playing: {:?}", err);
+        }
+    }
+}
+
+pub fn finish_loading(mut commands: Commands, screens: Query<Entity, With<LoadingScreen>>) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
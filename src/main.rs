@@ -1,20 +1,151 @@
+use bevy::ecs::schedule::ShouldRun;
+use bevy::gltf::{Gltf, GltfExtras};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+mod animation;
+mod audio;
+mod challenge;
+mod colliders;
+#[cfg(test)]
+mod determinism;
+mod facing;
+mod gentle;
+mod ghost;
+mod goal;
+mod levels;
+mod loading;
+mod menu;
+mod outcome;
+mod parental;
+mod particles;
+mod party;
+mod party_visuals;
+mod physics;
+mod reload;
+mod replay;
+mod restart;
+mod resume;
+mod ron_asset;
+mod save;
+mod simulation;
+mod species;
+mod surface;
+
+use party::PartyZone;
+use species::Species;
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum AppState {
     MainMenu,
+    // Shows progress while the level loads, then moves on to InGame by itself
+    Loading,
     InGame,
-    GameOver,
+    LevelComplete,
+    LevelFailed,
+    // Pushed on top of whatever is running, so popping it resumes play
+    ParentSettings,
+    // The level couldn't be loaded or connected, shows why instead of panicking
+    LoadFailed,
 }
 
 fn main() {
-    App::new()
-        .add_state(AppState::MainMenu)
-        .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(setup_game_scene))
-        .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(setup_ui))
-        .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(setup_ui))
-        .add_system_set(SystemSet::on_update(AppState::GameOver).with_system(any_key_to_restart))
+    let mut app = App::new();
+    app.add_state(AppState::MainMenu)
+        .add_system_set(
+            SystemSet::on_enter(AppState::MainMenu)
+                .with_system(resume::check_resume.label(resume::CheckResume))
+                .with_system(menu::open_menu.after(resume::CheckResume)),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::MainMenu)
+                .with_system(gentle::toggle_gentle_mode)
+                .with_system(menu::main_menu),
+        )
+        .add_system_set(SystemSet::on_resume(AppState::MainMenu).with_system(menu::resume_menu))
+        .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(menu::close_menu))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Loading)
+                .with_system(simulation::pause_for_loading)
+                .with_system(setup_game_scene)
+                .with_system(animation::load_animation_library)
+                .with_system(colliders::load_collider_cache)
+                .with_system(loading::start_loading),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Loading).with_system(loading::update_loading),
+        )
+        .add_system_set(SystemSet::on_exit(AppState::Loading).with_system(loading::finish_loading))
+        .add_system_set(
+            SystemSet::on_enter(AppState::InGame)
+                .with_system(simulation::start_playing)
+                .with_system(setup_ui)
+                .with_system(audio::start_level_audio)
+                .with_system(outcome::start_clock.label(outcome::StartClock))
+                .with_system(resume::restore_level.after(outcome::StartClock))
+                .with_system(challenge::start_challenge)
+                .with_system(replay::start_replay)
+                .with_system(ghost::start_ghost),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(challenge::update_countdown)
+                .with_system(ghost::move_ghosts)
+                .with_system(resume::autosave)
+                .with_system(gentle::parent_combo)
+                .with_system(parental::track_playtime),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::InGame)
+                .with_system(audio::stop_level_audio)
+                .with_system(parental::end_wind_down)
+                .with_system(replay::finish_replay)
+                .with_system(ghost::remove_ghosts)
+                .with_system(resume::discard_save)
+                .with_system(challenge::remove_countdown),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::LevelComplete)
+                .with_system(challenge::award_medal.label(challenge::AwardMedal))
+                .with_system(setup_ui.after(challenge::AwardMedal))
+                .with_system(ghost::save_ghost.after(challenge::AwardMedal)),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::LevelComplete).with_system(restart::end_screen_choice),
+        )
+        .add_system_set(SystemSet::on_enter(AppState::LevelFailed).with_system(setup_ui))
+        .add_system_set(
+            SystemSet::on_update(AppState::LevelFailed).with_system(restart::end_screen_choice),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::ParentSettings).with_system(parental::open_settings),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::ParentSettings).with_system(parental::settings_menu),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::ParentSettings).with_system(parental::close_settings),
+        )
+        .init_resource::<parental::Session>()
+        .add_startup_system(parental::load_parental)
+        .add_startup_system(ghost::load_ghost_settings)
+        .add_system(parental::parent_gate)
+        .add_system(parental::close_requested)
+        .init_resource::<outcome::LevelClock>()
+        .init_resource::<challenge::ChallengeSettings>()
+        .init_resource::<challenge::ChallengeResult>()
+        .init_resource::<gentle::GentleMode>()
+        .init_resource::<ghost::GhostRecorder>()
+        .init_resource::<resume::ResumeOffer>()
+        .init_resource::<restart::LevelSnapshot>()
+        .init_resource::<loading::LevelLoading>()
+        .add_event::<gentle::Cheer>()
+        .add_asset::<levels::LevelManifest>()
+        .add_asset_loader(ron_asset::RonAssetLoader::<levels::LevelManifest>::new(&[
+            "levels.ron",
+        ]))
+        .add_startup_system(levels::load_levels)
+        .add_startup_system(save::load_progress)
         .insert_resource(WindowDescriptor {
             title: "Combine".to_string(),
             width: 800.,
@@ -22,65 +153,132 @@ fn main() {
             present_mode: bevy::window::PresentMode::AutoVsync,
             ..default()
         })
+        // Window closing goes through parental::close_requested so it can be locked
+        .insert_resource(bevy::window::WindowSettings {
+            close_when_requested: false,
+            ..default()
+        })
         .add_plugins(DefaultPlugins)
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_event::<AnimalRecruited>()
+        .add_event::<AnimalFell>()
+        .init_resource::<audio::AudioMixer>()
+        .init_resource::<audio::AudioChannels>()
+        .add_startup_system(audio::load_audio)
+        .add_system(audio::gameplay_sounds)
+        .add_system(audio::party_audio)
+        .add_system(audio::footsteps)
+        .add_system(animation::animate_animals)
+        .init_resource::<facing::TurnRates>()
+        .init_resource::<colliders::ColliderCache>()
+        .add_event::<loading::LevelReady>()
+        .add_system(loading::watch_level_load)
         .add_system(connect_from_scene)
+        .add_system(reload::watch_level_reload)
+        .add_system(reload::finish_reload)
+        .add_system(reload::abandon_reload)
+        .add_system_set(SystemSet::on_enter(AppState::LoadFailed).with_system(setup_ui))
+        .add_system_set(
+            SystemSet::on_update(AppState::LoadFailed).with_system(loading::leave_load_error),
+        )
+        .add_system(colliders::save_collider_cache)
         .add_system(resize_notificator)
-        .add_system(gamepad_system)
+        .init_resource::<replay::Replayer>()
+        .init_resource::<replay::FrameInput>()
+        .add_startup_system(replay::load_replay)
+        .add_system(simulation::latch_buttons)
         .add_startup_system(setup)
         .add_startup_system(setup_physics)
         // .add_plugin(WorldInspectorPlugin::new())
-        .add_system(kill_player)
         .add_system(follow_cam)
-        .add_system(player_collectables)
-        .add_system(start_the_party)
-        .add_system(party)
-        .add_system(gameover_checker)
+        .init_resource::<party_visuals::PartyLooks>()
+        .add_startup_system(party_visuals::setup_party_visuals)
+        .add_system(party_visuals::dress_up_party_animals)
+        .add_system(party_visuals::pulse_party_materials)
+        .add_system_to_stage(CoreStage::PostUpdate, party_visuals::undress_party_animals)
+        .add_asset::<particles::ParticlePresets>()
+        .add_asset_loader(
+            ron_asset::RonAssetLoader::<particles::ParticlePresets>::new(&["particles.ron"]),
+        )
+        .add_startup_system(particles::setup_particles)
+        .add_system(particles::reload_particle_materials)
+        .add_system(particles::burst_particles)
+        .add_system(particles::continuous_particles)
+        .add_system(particles::update_particles)
         .add_startup_system(setup_ui)
         .insert_resource(GameResources {
             scene_entity: None,
-            party_material: StandardMaterial {
-                base_color: Color::Rgba {
-                    red: 0.,
-                    green: 1.,
-                    blue: 0.,
-                    alpha: 1.,
-                },
-                ..default()
-            },
             ui_node: None,
-            despawned_party_animals_count: 0,
-        })
-        .run();
+            delivered_animals_count: 0,
+            total_animals_count: 0,
+            herd_home_count: 0,
+            outcome: None,
+            current_level: "animals".to_string(),
+        });
+    simulation::add_physics(&mut app);
+    simulation::add_simulation(&mut app, simulation_systems());
+    app.run();
 }
 
-fn any_key_to_restart(
-    mut commands: Commands,
-    mut game_resources: ResMut<GameResources>,
-    mut app_state: ResMut<State<AppState>>,
-    buttons: Res<Input<GamepadButton>>,
-    keys: Res<Input<KeyCode>>,
-) {
-    if buttons.get_just_pressed().count() > 0 || keys.get_just_pressed().count() > 0 {
-        match game_resources.scene_entity {
-            Some(entity) => {
-                commands.entity(entity).despawn_recursive();
-            }
-            _ => {}
-        }
-        game_resources.despawned_party_animals_count = 0;
-        app_state.set(AppState::MainMenu).unwrap();
+// Everything that moves the herd or decides how the level ends runs on the fixed simulation
+// tick, in the order it's added here. Each system is pinned after the one before it, the stage
+// is free to shuffle systems that have no ordering between them, so the same input could play out
+// differently
+fn simulation_systems() -> SystemStage {
+    SystemStage::single_threaded()
+        .with_system(gamepad_system.label(replay::ReadInput))
+        .with_system(
+            surface::detect_footing
+                .label(surface::DetectFooting)
+                .after(replay::ReadInput),
+        )
+        .with_system(move_herd.after(surface::DetectFooting))
+        .with_system(kill_player.after(move_herd))
+        .with_system(lose_stragglers.after(kill_player))
+        .with_system(player_collectables.after(lose_stragglers))
+        .with_system(party::start_the_party.after(player_collectables))
+        .with_system(party::party.after(party::start_the_party))
+        .with_system(party::go_home.after(party::party))
+        .with_system(facing::turn_animals.after(party::go_home))
+        .with_system(outcome::herd_checker.after(facing::turn_animals))
+        .with_system(goal::track_goal.after(outcome::herd_checker))
+        .with_system(goal::check_goal.after(goal::track_goal))
+        .with_system_set(
+            SystemSet::new()
+                .with_run_criteria(in_game)
+                .after(goal::check_goal)
+                .with_system(outcome::tick_clock)
+                .with_system(gentle::regroup_stragglers.after(outcome::tick_clock))
+                .with_system(gentle::cheer_on_any_button.after(gentle::regroup_stragglers))
+                .with_system(ghost::record_ghost.after(gentle::cheer_on_any_button)),
+        )
+}
+
+// SystemSet::on_update keeps re-running its systems unless the state driver runs in the same
+// stage, which it doesn't in the simulation
+fn in_game(app_state: Res<State<AppState>>) -> ShouldRun {
+    if app_state.current() == &AppState::InGame {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::single_match)]
 fn setup_ui(
     app_state: ResMut<State<AppState>>,
     mut game_resources: ResMut<GameResources>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    party_animals: Query<(), With<PartyAnimal>>,
-    collectables: Query<(), With<Collectable>>,
+    level_clock: Res<outcome::LevelClock>,
+    challenge_result: Res<challenge::ChallengeResult>,
+    load_error: Option<Res<loading::LoadError>>,
+    // Not there yet when the first screen is set up at startup
+    levels: Option<Res<levels::Levels>>,
+    manifests: Res<Assets<levels::LevelManifest>>,
 ) {
+    let next_level = levels
+        .and_then(|levels| levels.next(&manifests, &game_resources.current_level))
+        .map(|level| level.title.clone());
     match game_resources.ui_node {
         Some(entity) => {
             commands.entity(entity).despawn_recursive();
@@ -121,20 +319,20 @@ fn setup_ui(
                                 &AppState::InGame => {
                                     "Combine your animal herd and take them to the exit!".to_string()
                                 }
-                                &AppState::GameOver => {
-                                    let party_total = party_animals.iter().count() as i32 + game_resources.despawned_party_animals_count;
-                                    let potential_total = collectables.iter().count() as i32 + game_resources.despawned_party_animals_count;
-                                    if party_total == potential_total - 1 { // 1 animal missing? Close enough :/
-                                        "WOW! You got all the animals! You win the game for sure! Press any key/button to try again :)".to_string()
-                                    } else {
-                                        format!(
-                                            "Congrats! You got {} out of a possible {} animals to the exit! Press any key/button to try again :)",
-                                            party_total,
-                                            potential_total)
-                                    }
-                                        
-                                },
-                                &AppState::MainMenu => "Press any button to start!".to_string(),
+                                &AppState::LevelComplete | &AppState::LevelFailed => {
+                                    outcome::summary(
+                                        &game_resources,
+                                        &level_clock,
+                                        &challenge_result,
+                                        next_level.as_deref(),
+                                    )
+                                }
+                                &AppState::MainMenu
+                                | &AppState::Loading
+                                | &AppState::ParentSettings => String::new(),
+                                &AppState::LoadFailed => {
+                                    loading::error_message(load_error.as_deref())
+                                }
                             },
                             TextStyle {
                                 font: asset_server.load("FredokaOne-Regular.ttf"),
@@ -153,32 +351,38 @@ fn setup_ui(
         .id());
 }
 
-fn party(time: Res<Time>, mut party_zone: Query<(&mut Transform, &PartyZone)>) {
-    for (mut transform, party_zone) in party_zone.iter_mut() {
-        let displacement = (time.seconds_since_startup() * 7.).sin().powf(1.).abs() * 1.;
-        transform.translation = party_zone.bob_position + Vec3::Y * displacement as f32;
-    }
-}
-
 fn follow_cam(
-    mut commands: Commands,
-    player: Query<(Entity, &GlobalTransform, &Transform), With<Player>>,
+    player: Query<&GlobalTransform, With<Player>>,
     mut camera: Query<&mut Transform, (With<Camera3d>, Without<Player>)>,
 ) {
     for mut camera_global_transform in camera.iter_mut() {
-        let average_player_position = player.iter().fold(Vec3::ZERO, |sum, (_, transform, _)| {
-            sum + transform.translation()
-        }) / player.iter().count() as f32;
+        let average_player_position = player
+            .iter()
+            .fold(Vec3::ZERO, |sum, transform| sum + transform.translation())
+            / player.iter().count() as f32;
         camera_global_transform.translation =
             average_player_position + camera_global_transform.rotation * Vec3::Z * 50.;
-        for (entity, global_transform, _) in player.iter() {
-            if global_transform
-                .translation()
-                .distance(average_player_position)
-                > 20.
-            {
-                commands.entity(entity).remove::<Player>();
-            }
+    }
+}
+
+// Runs on the simulation tick, so it measures the simulated transforms rather than the
+// interpolated ones the camera follows
+fn lose_stragglers(
+    mut commands: Commands,
+    gentle_mode: Res<gentle::GentleMode>,
+    player: Query<(Entity, &Transform), With<Player>>,
+) {
+    if gentle_mode.enabled {
+        return;
+    }
+    let average_player_position =
+        match gentle::herd_center(player.iter().map(|(_, transform)| transform.translation)) {
+            Some(position) => position,
+            None => return,
+        };
+    for (entity, transform) in player.iter() {
+        if transform.translation.distance(average_player_position) > 20. {
+            commands.entity(entity).remove::<Player>();
         }
     }
 }
@@ -198,25 +402,33 @@ fn setup_physics(
 }
 
 const CHARACTER_SPEED: f32 = 12.;
+const ANIMAL_RADIUS: f32 = 2.;
 
 #[derive(Component)]
 struct Player {
     spawn_position: Vec3,
 }
 
-#[derive(Component)]
-struct PartyZone {
-    bob_position: Vec3,
-}
-
 #[derive(Component)]
 struct PartyAnimal {}
 
 struct GameResources {
-    party_material: StandardMaterial,
     scene_entity: Option<Entity>,
     ui_node: Option<Entity>,
-    despawned_party_animals_count: i32,
+    delivered_animals_count: i32,
+    total_animals_count: i32,
+    herd_home_count: i32,
+    outcome: Option<outcome::LevelOutcome>,
+    current_level: String,
+}
+
+struct AnimalRecruited {
+    species: Species,
+    position: Vec3,
+}
+
+struct AnimalFell {
+    respawn_position: Vec3,
 }
 
 #[derive(Component)]
@@ -225,11 +437,14 @@ struct Collectable {}
 #[derive(Component)]
 struct KillWall;
 
+#[allow(clippy::single_match, clippy::collapsible_match)]
 fn kill_player(
     mut commands: Commands,
+    gentle_mode: Res<gentle::GentleMode>,
     mut collisions: EventReader<CollisionEvent>,
+    mut fell: EventWriter<AnimalFell>,
     kill_wall: Query<&KillWall>,
-    mut players: Query<(&Player, &mut Transform, &mut Velocity)>,
+    mut players: Query<(Entity, &Name, &Player, &mut Transform, &mut Velocity)>,
 ) {
     let mut player_count = players.iter().count();
     for collision in collisions.iter() {
@@ -237,14 +452,27 @@ fn kill_player(
             &CollisionEvent::Started(a, b, _) => {
                 if [a, b].iter().any(|&entity| kill_wall.contains(entity)) {
                     for &entity in [a, b].iter() {
+                        // Gentle mode drops fallen animals back in next to the rest of the herd
+                        let herd_center = gentle::herd_center(
+                            players
+                                .iter()
+                                .filter(|(other, ..)| *other != entity)
+                                .map(|(_, _, _, transform, _)| transform.translation),
+                        )
+                        .filter(|_| gentle_mode.enabled);
                         match players.get_mut(entity) {
-                            Ok((player, mut transform, mut velocity)) => {
-                                if player_count > 1 {
+                            Ok((_, name, player, mut transform, mut velocity)) => {
+                                if player_count > 1 && !gentle_mode.enabled {
                                     commands.entity(entity).remove::<Player>();
                                     player_count -= 1;
                                 }
 
-                                transform.translation = player.spawn_position;
+                                let respawn_position = match herd_center {
+                                    Some(center) => gentle::beside_herd(center, name),
+                                    None => player.spawn_position,
+                                };
+                                fell.send(AnimalFell { respawn_position });
+                                transform.translation = respawn_position;
                                 velocity.linvel = Vec3::ZERO;
                             }
                             _ => {}
@@ -257,86 +485,14 @@ fn kill_player(
     }
 }
 
-fn start_the_party(
-    mut commands: Commands,
-    mut game_resources: ResMut<GameResources>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut collisions: EventReader<CollisionEvent>,
-    players: Query<&Children, With<Player>>,
-    party_zones: Query<(), With<PartyZone>>,
-    mut material_handles: Query<Entity, With<Handle<StandardMaterial>>>,
-    party_animals: Query<Entity, With<PartyAnimal>>,
-) {
-    let mut party_count = party_animals.iter().count();
-    let mut party_iter = party_animals.iter();
-    for collision in collisions.iter() {
-        match collision {
-            &CollisionEvent::Started(a, b, _) => {
-                if [a, b].iter().any(|&entity| party_zones.contains(entity)) {
-                    for &entity in [a, b].iter() {
-                        match players.get(entity) {
-                            Ok(children) => {
-                                if party_count > 10 {
-                                    match party_iter.next() {
-                                        Some(entity) => {
-                                            commands.entity(entity).despawn_recursive();
-                                        }
-                                        _ => {}
-                                    }
-                                    party_count -= 1;
-                                    print!(
-                                        "Despawned count {}",
-                                        game_resources.despawned_party_animals_count
-                                    );
-                                    game_resources.despawned_party_animals_count += 1;
-                                }
-                                commands
-                                    .entity(entity)
-                                    .remove::<Player>()
-                                    .insert(PartyAnimal {});
-                                for &child in children.iter() {
-                                    match material_handles.get_mut(child) {
-                                        Ok(material_entity) => {
-                                            let party_material = materials
-                                                .add(game_resources.party_material.clone());
-                                            commands.entity(material_entity).insert(party_material);
-                                        }
-                                        Err(_) => {}
-                                    }
-                                }
-                            }
-                            Err(_) => {}
-                        };
-                    }
-                }
-            }
-            _ => {}
-        }
-    }
-}
-
-fn gameover_checker(
-    mut app_state: ResMut<State<AppState>>,
-    game_resources: Res<GameResources>,
-    players: Query<(), With<Player>>,
-    party_animals: Query<(), With<PartyAnimal>>,
-) {
-    if app_state.current() == &AppState::InGame {
-        if players.iter().count() == 0 {
-            let score_total =
-                party_animals.iter().count() as i32 + game_resources.despawned_party_animals_count;
-            print!("Game Over! Your score is {}", score_total);
-            app_state.set(AppState::GameOver).unwrap();
-        }
-    }
-}
-
+#[allow(clippy::type_complexity, clippy::single_match, clippy::collapsible_match)]
 fn player_collectables(
     mut commands: Commands,
     mut collisions: EventReader<CollisionEvent>,
+    mut recruited: EventWriter<AnimalRecruited>,
     players: Query<&Player>,
     collectables: Query<
-        &GlobalTransform,
+        (&GlobalTransform, &Species),
         (With<Collectable>, Without<Player>, Without<PartyAnimal>),
     >,
 ) {
@@ -346,10 +502,14 @@ fn player_collectables(
                 if [a, b].iter().any(|&entity| players.contains(entity)) {
                     for &entity in [a, b].iter() {
                         match collectables.get(entity) {
-                            Ok(&transform) => {
+                            Ok((transform, &species)) => {
                                 commands.entity(entity).insert(Player {
                                     spawn_position: transform.translation(),
                                 });
+                                recruited.send(AnimalRecruited {
+                                    species,
+                                    position: transform.translation(),
+                                });
                             }
                             Err(_) => {}
                         };
@@ -361,114 +521,189 @@ fn player_collectables(
     }
 }
 
+// Shared with the determinism test so it simulates the same bodies as the game
+fn animal_physics() -> impl Bundle {
+    (
+        RigidBody::Dynamic,
+        Velocity { ..default() },
+        Collider::ball(ANIMAL_RADIUS),
+        Restitution::coefficient(0.2),
+        LockedAxes::ROTATION_LOCKED,
+        ActiveEvents::COLLISION_EVENTS,
+        GravityScale(4.),
+        // How much they grip is down to the level, or a "friction" override, see PhysicsOverrides
+        Friction {
+            coefficient: 0.,
+            ..default()
+        },
+        Ccd::enabled(),
+        surface::Footing::default(),
+    )
+}
+
+fn is_player(name: &str) -> bool {
+    name.contains("Player")
+}
+
+fn is_collectable(name: &str) -> bool {
+    name.contains("Collectable")
+}
+
+fn is_party_zone(name: &str) -> bool {
+    name.starts_with("PartyZone")
+}
+
+fn is_goal(name: &str) -> bool {
+    name == "Goal"
+}
+
+#[allow(clippy::too_many_arguments)]
 fn connect_from_scene(
     mut app_state: ResMut<State<AppState>>,
-    named_entities: Query<(Entity, &Name, &Transform), Added<Name>>,
-    named_entities_with_children: Query<(Entity, &Name, &Children, &Transform), Added<Name>>,
-    meshes: Query<&Handle<Mesh>>,
-    mesh_assets: Res<Assets<Mesh>>,
+    mut game_resources: ResMut<GameResources>,
+    mut level_ready: EventReader<loading::LevelReady>,
+    named_entities: Query<(Entity, &Name, &Transform)>,
+    named_entities_with_children: Query<(Entity, &Name, &Children, &Transform)>,
+    extras: Query<&GltfExtras>,
+    names: Query<&Name>,
+    gltfs: Res<Assets<Gltf>>,
+    mut level_meshes: colliders::LevelMeshes,
+    mut level_loading: ResMut<loading::LevelLoading>,
+    mut snapshot: ResMut<restart::LevelSnapshot>,
     mut commands: Commands,
 ) {
+    // Only once the whole scene is spawned and loaded, see loading::watch_level_load
+    let scene_entities = match level_ready.iter().last() {
+        Some(level_ready) => &level_ready.entities,
+        None => return,
+    };
     let bunnies = named_entities
-        .iter()
-        .filter(|&(_, name, _)| name.contains("Player"))
+        .iter_many(scene_entities)
+        .filter(|&(_, name, _)| is_player(name.as_str()))
         .collect::<Vec<_>>();
     let collectables = named_entities
-        .iter()
-        .filter(|&(_, name, _)| name.contains("Collectable"));
+        .iter_many(scene_entities)
+        .filter(|&(_, name, _)| is_collectable(name.as_str()));
     for (entity, _, transform) in bunnies.clone() {
         commands.entity(entity).insert(Player {
             spawn_position: transform.translation,
         });
     }
 
-    for (entity, _, _) in collectables.chain(bunnies) {
-        commands
-            .entity(entity)
-            .insert(RigidBody::Dynamic)
-            .insert(Velocity { ..default() })
-            .insert(Collider::ball(2.0))
-            .insert(Restitution::coefficient(0.2))
-            .insert(LockedAxes::ROTATION_LOCKED)
-            .insert(ActiveEvents::COLLISION_EVENTS)
-            .insert(GravityScale(4.))
-            .insert(Friction {
-                coefficient: 0.,
-                ..default()
-            })
-            // .insert(Ccd::enabled()) // Breaks with TriMesh :(
+    for (entity, name, transform) in collectables.chain(bunnies) {
+        game_resources.total_animals_count += 1;
+        snapshot.record_animal(entity, transform, is_player(name.as_str()));
+        let mut animal = commands.entity(entity);
+        animal
+            .insert(Species::from_name(name.as_str()))
+            .insert(facing::Facing::new(transform))
+            .insert(AnimationPlayer::default())
+            .insert(animation::AnimalAnimator::default())
+            .insert_bundle(animal_physics())
+            .insert(simulation::Interpolated::new(transform))
             .insert(Collectable {});
+        physics::PhysicsOverrides::from_extras(extras.get(entity).ok()).apply_body(&mut animal);
+    }
+    let proxies = named_entities
+        .iter_many(scene_entities)
+        .filter(|&(_, name, _)| colliders::is_proxy(name.as_str()));
+    for (entity, name, _) in proxies {
+        match colliders::proxy_collider(name.as_str()) {
+            Some(collider) => {
+                let mut proxy = commands.entity(entity);
+                proxy
+                    .insert(collider)
+                    .insert(Visibility { is_visible: false });
+                physics::PhysicsOverrides::from_extras(extras.get(entity).ok())
+                    .apply_surface(&mut proxy);
+                if let Some(surface) = surface::Surface::from_extras(extras.get(entity).ok()) {
+                    proxy.insert(surface);
+                }
+            }
+            None => println!("Unknown collider proxy: {}", name),
+        }
     }
-    let level = named_entities_with_children
-        .iter()
-        .filter(|&(_, name, _, _)| name.eq(&Name::new("Level")));
     let party_zone = named_entities_with_children
-        .iter()
-        .filter(|&(_, name, _, _)| name.eq(&Name::new("PartyZone")));
+        .iter_many(scene_entities)
+        .filter(|&(_, name, _, _)| is_party_zone(name.as_str()));
     let goal = named_entities_with_children
-        .iter()
-        .filter(|&(_, name, _, _)| name.eq(&Name::new("Goal")));
-    for (entity, name, children, _) in level {
-        let (child_mesh_entities, _): (Vec<_>, Vec<_>) = children
-            .iter()
-            .map(|&child| meshes.get(child))
-            .partition(Result::is_ok);
-        let child_mesh_entities: Vec<_> = child_mesh_entities
-            .into_iter()
-            .map(Result::unwrap)
-            .collect();
-        for mesh in child_mesh_entities {
-            commands.entity(entity).insert(
-                Collider::from_bevy_mesh(
-                    mesh_assets.get(mesh).unwrap(),
-                    &ComputedColliderShape::TriMesh,
-                )
-                .unwrap(),
-            );
+        .iter_many(scene_entities)
+        .filter(|&(_, name, _, _)| is_goal(name.as_str()))
+        .collect::<Vec<_>>();
+    // Without a goal the level could never finish loading
+    if goal.is_empty() {
+        let reason = format!("{}.gltf has no Goal", game_resources.current_level);
+        loading::fail(&mut commands, &mut app_state, reason);
+        return;
+    }
+    // Any other node with a mesh is solid level geometry, whatever it's called or however deep
+    // it's nested
+    let level = game_resources
+        .scene_entity
+        .map(|scene| {
+            level_meshes.geometry_nodes(scene, &|name| {
+                is_player(name)
+                    || is_collectable(name)
+                    || colliders::is_proxy(name)
+                    || is_party_zone(name)
+                    || is_goal(name)
+            })
+        })
+        .unwrap_or_default();
+    let material_surfaces = surface::material_surfaces(gltfs.get(level_loading.gltf()));
+    let goal_entities = goal.iter().map(|&(entity, ..)| entity);
+    for entity in level.into_iter().chain(goal_entities) {
+        if let Err(reason) = level_meshes.build_colliders(&mut commands, entity, &material_surfaces)
+        {
+            loading::fail(&mut commands, &mut app_state, reason);
+            return;
+        }
+        if let Ok(name) = names.get(entity) {
+            println!("Level Geometry Found: {}", name);
         }
-        println!("Level Geometry Found: {}", name);
     }
     for (entity, name, children, _) in goal {
-        let (child_mesh_entities, _): (Vec<_>, Vec<_>) = children
-            .iter()
-            .map(|&child| meshes.get(child))
-            .partition(Result::is_ok);
-        let child_mesh_entities: Vec<_> = child_mesh_entities
-            .into_iter()
-            .map(Result::unwrap)
-            .collect();
-        for mesh in child_mesh_entities {
-            commands.entity(entity).insert(
-                Collider::from_bevy_mesh(
-                    mesh_assets.get(mesh).unwrap(),
-                    &ComputedColliderShape::TriMesh,
-                )
-                .unwrap(),
-            );
+        // Animals walk into the goal rather than bumping off it
+        for &child in children.iter() {
+            commands.entity(child).insert(Sensor);
         }
-        app_state.set(AppState::InGame).unwrap();
+        commands
+            .entity(entity)
+            .insert(goal::GoalZone::new(extras.get(entity).ok()));
+        level_loading.connected = true;
         println!("Goal Geometry Found: {}", name);
     }
     for (entity, name, _, transform) in party_zone {
         commands
             .entity(entity)
             .insert(Collider::cuboid(1., 1., 1.))
-            .insert(PartyZone {
-                bob_position: transform.translation,
-            })
-            .insert(RigidBody::KinematicPositionBased);
+            .insert(PartyZone::new(
+                transform.translation,
+                extras.get(entity).ok(),
+            ))
+            .insert(RigidBody::KinematicPositionBased)
+            .insert(simulation::Interpolated::new(transform));
         // .insert(Ccd::enabled());
         println!("Party Zone Geometry Found: {}", name);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn gamepad_system(
     gamepads: Res<Gamepads>,
     axes: Res<Axis<GamepadAxis>>,
     keys: Res<Input<KeyCode>>,
-    camera: Query<&GlobalTransform, With<Camera>>,
-    mut player: Query<(&mut Velocity, &mut Transform), With<Player>>,
+    app_state: Res<State<AppState>>,
+    session: Res<parental::Session>,
+    mut latch: ResMut<simulation::ButtonLatch>,
+    mut replayer: ResMut<replay::Replayer>,
+    mut frame_input: ResMut<replay::FrameInput>,
 ) {
+    if app_state.current() == &AppState::ParentSettings {
+        *frame_input = replay::FrameInput::default();
+        latch.cheer = false;
+        return;
+    }
     let mut raw_input = Vec3 {
         z: if keys.pressed(KeyCode::Up) { -1. } else { 0. }
             + if keys.pressed(KeyCode::Down) { 1. } else { 0. },
@@ -508,6 +743,25 @@ fn gamepad_system(
         raw_input = raw_input.normalize() * (raw_input.length() - 0.05).max(0.) / 0.95;
     }
 
+    *frame_input = replayer.frame_input(
+        app_state.current() == &AppState::InGame,
+        replay::FrameInput {
+            movement: [raw_input.x, raw_input.z],
+            cheer: std::mem::take(&mut latch.cheer),
+            speed: session.speed_factor(),
+        },
+    );
+}
+
+fn move_herd(
+    time: Res<simulation::SimulationTime>,
+    frame_input: Res<replay::FrameInput>,
+    camera: Query<&GlobalTransform, With<Camera>>,
+    mut player: Query<(&mut Velocity, &mut facing::Facing, &surface::Footing), With<Player>>,
+) {
+    let speed = CHARACTER_SPEED * frame_input.speed;
+    let raw_input = frame_input.movement();
+
     let camera_relative_input = if let Ok(camera_transform) = camera.get_single() {
         let (_, camera_rotation, _) = camera_transform.to_scale_rotation_translation();
         let flat_camera_rotation = Quat::from_axis_angle(
@@ -525,36 +779,48 @@ fn gamepad_system(
        raw_input 
     };
 
-    for (mut velocity, mut transform) in player.iter_mut() {
+    for (mut velocity, mut facing, footing) in player.iter_mut() {
+        // In the air the herd steers like it's on grass
+        let surface = footing.surface.unwrap_or_default().params();
+        let target = camera_relative_input * speed * surface.speed;
+        let current = velocity.linvel * Vec3::new(1., 0., 1.);
+        let rate = if target.length() > current.length() {
+            surface.acceleration
+        } else {
+            surface.deceleration
+        };
+        let horizontal = current.lerp(target, 1. - (-rate * time.delta_seconds()).exp());
         velocity.linvel = Vec3 {
-            x: camera_relative_input.x * CHARACTER_SPEED,
-            z: camera_relative_input.z * CHARACTER_SPEED,
+            x: horizontal.x,
+            z: horizontal.z,
             ..velocity.linvel
         };
+        if let Some(bounce) = surface.bounce {
+            if velocity.linvel.y <= 0. {
+                velocity.linvel.y = bounce;
+            }
+        }
         if camera_relative_input.length() > 0.25 {
-            transform.rotation = Quat::from_axis_angle(
-                Vec3::Y,
-                3.14 / 2.
-                    + Vec2 {
-                        x: camera_relative_input.x,
-                        y: camera_relative_input.z,
-                    }
-                    .angle_between(Vec2::X),
-            )
+            facing.desired = Some(Vec2 {
+                x: camera_relative_input.x,
+                y: camera_relative_input.z,
+            });
         }
     }
 }
 
 fn setup_game_scene(
     mut game_resources: ResMut<GameResources>,
+    mut snapshot: ResMut<restart::LevelSnapshot>,
     mut commands: Commands,
     asset_server: ResMut<AssetServer>,
 ) {
+    *snapshot = restart::LevelSnapshot::default();
     // load scene
     game_resources.scene_entity = Some(
         commands
             .spawn_bundle(SceneBundle {
-                scene: asset_server.load("animals.gltf#Scene0"),
+                scene: asset_server.load(&format!("{}.gltf#Scene0", game_resources.current_level)),
                 ..default()
             })
             .id(),
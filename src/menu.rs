@@ -0,0 +1,264 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::challenge::ChallengeSettings;
+use crate::gentle::GentleMode;
+use crate::levels::{LevelManifest, Levels};
+use crate::parental::{ParentalSettings, PlayLimits};
+use crate::resume::ResumeOffer;
+use crate::{AppState, GameResources};
+
+const FOCUS_COLOR: Color = Color::rgb(1., 0.8, 0.3);
+
+#[derive(Clone, Copy, PartialEq)]
+enum MenuItem {
+    // Only there when a level was left part way through
+    Continue,
+    Play,
+    LevelSelect,
+    // Races the level's medal par times
+    Timed,
+    Settings,
+    Quit,
+}
+
+#[derive(Component)]
+pub struct MainMenu {
+    items: Vec<MenuItem>,
+    selected: usize,
+    // Set when the settings close, so the press that closed them doesn't pick something here
+    skip_input: bool,
+}
+
+#[derive(Component)]
+pub struct MenuEntry(usize);
+
+#[derive(Component)]
+pub struct MenuHint;
+
+fn level_title<'a>(
+    levels: &Levels,
+    manifests: &'a Assets<LevelManifest>,
+    name: &'a str,
+) -> &'a str {
+    levels
+        .get(manifests, name)
+        .map_or(name, |level| level.title.as_str())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn entry_text(
+    item: MenuItem,
+    play_allowed: bool,
+    resume_offer: &ResumeOffer,
+    current_level: &str,
+    timed: bool,
+    parental_settings: &ParentalSettings,
+    levels: &Levels,
+    manifests: &Assets<LevelManifest>,
+) -> String {
+    match item {
+        // Out of playtime the menu stays up until a grown-up changes the limits
+        MenuItem::Continue | MenuItem::Play if !play_allowed => {
+            "Time for a break (ask a grown-up)".to_string()
+        }
+        MenuItem::Continue => format!(
+            "Continue {}",
+            level_title(levels, manifests, resume_offer.level().unwrap_or_default())
+        ),
+        MenuItem::Play => "Play".to_string(),
+        MenuItem::LevelSelect => format!(
+            "Level: < {} >",
+            level_title(levels, manifests, current_level)
+        ),
+        MenuItem::Timed => format!("Timed run: < {} >", if timed { "On" } else { "Off" }),
+        MenuItem::Settings if parental_settings.locked => {
+            "Settings (hold both sticks or both Shift keys)".to_string()
+        }
+        MenuItem::Settings => "Settings".to_string(),
+        MenuItem::Quit if parental_settings.locked => "Quit (ask a grown-up)".to_string(),
+        MenuItem::Quit => "Quit".to_string(),
+    }
+}
+
+// After resume::check_resume, so a saved level shows up as Continue
+pub fn open_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    resume_offer: Res<ResumeOffer>,
+) {
+    let items = resume_offer
+        .level()
+        .map(|_| MenuItem::Continue)
+        .into_iter()
+        .chain([
+            MenuItem::Play,
+            MenuItem::LevelSelect,
+            MenuItem::Timed,
+            MenuItem::Settings,
+            MenuItem::Quit,
+        ])
+        .collect::<Vec<_>>();
+    let font = asset_server.load("FredokaOne-Regular.ttf");
+    let text = |value: &str, font_size: f32| {
+        TextBundle::from_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::all(Val::Px(8.0)),
+            ..default()
+        })
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgb(0.1, 0.15, 0.1).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(text("Animal Aggregator", 50.0));
+            for index in 0..items.len() {
+                parent.spawn_bundle(text("", 30.0)).insert(MenuEntry(index));
+            }
+            parent.spawn_bundle(text("", 20.0)).insert(MenuHint);
+        })
+        .insert(MainMenu {
+            items,
+            selected: 0,
+            skip_input: false,
+        });
+}
+
+pub fn resume_menu(mut menus: Query<&mut MainMenu>) {
+    for mut menu in menus.iter_mut() {
+        menu.skip_input = true;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn main_menu(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    levels: Res<Levels>,
+    manifests: Res<Assets<LevelManifest>>,
+    limits: PlayLimits,
+    gentle_mode: Res<GentleMode>,
+    mut challenge_settings: ResMut<ChallengeSettings>,
+    mut game_resources: ResMut<GameResources>,
+    mut resume_offer: ResMut<ResumeOffer>,
+    mut app_state: ResMut<State<AppState>>,
+    mut exit: EventWriter<AppExit>,
+    mut menus: Query<&mut MainMenu>,
+    mut entries: Query<(&MenuEntry, &mut Text), Without<MenuHint>>,
+    mut hints: Query<&mut Text, With<MenuHint>>,
+) {
+    let mut menu = match menus.get_single_mut() {
+        Ok(menu) => menu,
+        Err(_) => return,
+    };
+    let pressed = |key: KeyCode, button_type: GamepadButtonType| {
+        keys.just_pressed(key)
+            || buttons
+                .get_just_pressed()
+                .any(|button| button.button_type == button_type)
+    };
+    let up = pressed(KeyCode::Up, GamepadButtonType::DPadUp);
+    let down = pressed(KeyCode::Down, GamepadButtonType::DPadDown);
+    let left = pressed(KeyCode::Left, GamepadButtonType::DPadLeft);
+    let right = pressed(KeyCode::Right, GamepadButtonType::DPadRight);
+    let confirm = pressed(KeyCode::Return, GamepadButtonType::South);
+    let play_allowed = limits.play_allowed();
+
+    if !std::mem::take(&mut menu.skip_input) {
+        let count = menu.items.len();
+        if up {
+            menu.selected = (menu.selected + count - 1) % count;
+        }
+        if down {
+            menu.selected = (menu.selected + 1) % count;
+        }
+        match menu.items[menu.selected] {
+            // Another change already queued this frame wins, eg. the settings opening on F1
+            MenuItem::Continue if confirm && play_allowed => {
+                match app_state.set(AppState::Loading) {
+                    Ok(_) => resume_offer.continue_game(&mut commands, &mut game_resources),
+                    Err(err) => println!("Couldn't continue the level: {:?}", err),
+                }
+                return;
+            }
+            MenuItem::Play if confirm && play_allowed => {
+                if let Err(err) = app_state.set(AppState::Loading) {
+                    println!("Couldn't start the level: {:?}", err);
+                }
+                return;
+            }
+            MenuItem::LevelSelect if left || right || confirm => {
+                let all = levels.all(&manifests);
+                if let Some(index) = all
+                    .iter()
+                    .position(|level| level.name == game_resources.current_level)
+                {
+                    let step = if left { all.len() - 1 } else { 1 };
+                    game_resources.current_level = all[(index + step) % all.len()].name.clone();
+                }
+            }
+            MenuItem::Timed if left || right || confirm => {
+                challenge_settings.timed = !challenge_settings.timed;
+            }
+            MenuItem::Settings if confirm && !limits.settings.locked => {
+                if let Err(err) = app_state.push(AppState::ParentSettings) {
+                    println!("Couldn't open settings: {:?}", err);
+                }
+                return;
+            }
+            MenuItem::Quit if confirm && !limits.settings.locked => exit.send(AppExit),
+            _ => {}
+        }
+    }
+
+    for (entry, mut text) in entries.iter_mut() {
+        let item = menu.items[entry.0];
+        let label = entry_text(
+            item,
+            play_allowed,
+            &resume_offer,
+            &game_resources.current_level,
+            challenge_settings.timed,
+            &limits.settings,
+            &levels,
+            &manifests,
+        );
+        let section = &mut text.sections[0];
+        if entry.0 == menu.selected {
+            section.value = format!("> {} <", label);
+            section.style.color = FOCUS_COLOR;
+        } else {
+            section.value = label;
+            section.style.color = Color::WHITE;
+        }
+    }
+    for mut text in hints.iter_mut() {
+        text.sections[0].value = format!(
+            "Press G or Select for gentle mode: {}",
+            if gentle_mode.enabled { "On" } else { "Off" }
+        );
+    }
+}
+
+pub fn close_menu(mut commands: Commands, menus: Query<Entity, With<MainMenu>>) {
+    for entity in menus.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
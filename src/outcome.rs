@@ -0,0 +1,265 @@
+use bevy::prelude::*;
+
+use crate::challenge::ChallengeResult;
+use crate::gentle::GentleMode;
+use crate::goal::GoalZone;
+use crate::simulation::SimulationTime;
+use crate::{AppState, GameResources, Player};
+
+const DELIVERED_POINTS: i32 = 100;
+const HERD_HOME_POINTS: i32 = 150;
+const SECOND_LEFT_POINTS: f32 = 10.;
+// Share of the herd that has to reach the party for the level to count as complete
+const MIN_DELIVERED_SHARE: f32 = 0.5;
+const MENU_HINT: &str = "Esc/B for the main menu";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CompleteReason {
+    AllDelivered,
+    // Enough of the herd made it to the party, but not all of it
+    MostDelivered,
+    ReachedGoal,
+    // Gentle mode only ends through the parent combo
+    EndedByParent,
+    // Session or daily playtime ran out
+    Bedtime,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FailReason {
+    HerdLost,
+    // Some made it to the party, too few to count
+    TooFewDelivered,
+    TimeUp,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LevelOutcome {
+    Complete(CompleteReason),
+    Failed(FailReason),
+}
+
+impl LevelOutcome {
+    // Finished by getting the herd somewhere, rather than a grown-up or the clock ending play
+    pub fn herd_completed(&self) -> bool {
+        matches!(
+            self,
+            LevelOutcome::Complete(
+                CompleteReason::AllDelivered
+                    | CompleteReason::MostDelivered
+                    | CompleteReason::ReachedGoal
+            )
+        )
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct StartClock;
+
+#[derive(Default)]
+pub struct LevelClock {
+    pub elapsed: f32,
+    pub limit: Option<f32>,
+    // Slowest par time in timed mode
+    pub challenge_limit: Option<f32>,
+}
+
+pub fn score(outcome: LevelOutcome, game_resources: &GameResources, clock: &LevelClock) -> i32 {
+    let animals = game_resources.delivered_animals_count * DELIVERED_POINTS
+        + game_resources.herd_home_count * HERD_HOME_POINTS;
+    match outcome {
+        LevelOutcome::Complete(_) => {
+            let time_bonus = clock
+                .limit
+                .map(|limit| ((limit - clock.elapsed).max(0.) * SECOND_LEFT_POINTS) as i32)
+                .unwrap_or(0);
+            animals + time_bonus
+        }
+        // Whatever made it to the party still counts, but nothing else
+        LevelOutcome::Failed(_) => game_resources.delivered_animals_count * DELIVERED_POINTS,
+    }
+}
+
+pub fn summary(
+    game_resources: &GameResources,
+    clock: &LevelClock,
+    challenge: &ChallengeResult,
+    next_level: Option<&str>,
+) -> String {
+    let outcome = match game_resources.outcome {
+        Some(outcome) => outcome,
+        None => return String::new(),
+    };
+    let party_total = game_resources.delivered_animals_count;
+    let potential_total = game_resources.total_animals_count;
+    let headline = match outcome {
+        LevelOutcome::Complete(CompleteReason::AllDelivered) => {
+            "WOW! You got all the animals to the party! You win the game for sure!".to_string()
+        }
+        LevelOutcome::Complete(CompleteReason::MostDelivered) => format!(
+            "Congrats! You got {} out of a possible {} animals to the party!",
+            party_total, potential_total
+        ),
+        LevelOutcome::Complete(CompleteReason::ReachedGoal) => format!(
+            "You made it home with {} animals in your herd and {} at the party!",
+            game_resources.herd_home_count, party_total
+        ),
+        LevelOutcome::Complete(CompleteReason::EndedByParent) => format!(
+            "What a lovely herd! {} animals went to the party. Time for a break!",
+            party_total
+        ),
+        LevelOutcome::Complete(CompleteReason::Bedtime) => format!(
+            "The animals are all tucked in! {} of them danced at the party today.",
+            party_total
+        ),
+        LevelOutcome::Failed(FailReason::HerdLost) => {
+            "Oh no, your herd wandered off! Nobody made it to the party.".to_string()
+        }
+        LevelOutcome::Failed(FailReason::TooFewDelivered) => format!(
+            "Only {} out of {} animals made it to the party, the rest wandered off!",
+            party_total, potential_total
+        ),
+        LevelOutcome::Failed(FailReason::TimeUp) => format!(
+            "Time's up! {} out of {} animals made it to the party.",
+            party_total, potential_total
+        ),
+    };
+    let medal = match challenge.medal {
+        Some(medal) if challenge.new_record => format!("\n{} medal! New record!", medal.label()),
+        Some(medal) => format!("\n{} medal!", medal.label()),
+        None => String::new(),
+    };
+    let next_step = match (outcome, next_level) {
+        (LevelOutcome::Complete(CompleteReason::Bedtime), _) => {
+            "Goodnight! See you next time :)".to_string()
+        }
+        (LevelOutcome::Complete(_), Some(next_level)) => format!(
+            "Press any key/button to play again :)\nRight for the next level, {}\n{}",
+            next_level, MENU_HINT
+        ),
+        (LevelOutcome::Complete(_), None) => {
+            format!("Press any key/button to play again :)\n{}", MENU_HINT)
+        }
+        (LevelOutcome::Failed(_), _) => {
+            format!("Press any key/button to try again!\n{}", MENU_HINT)
+        }
+    };
+    format!(
+        "{}\nScore: {}{}\n{}",
+        headline,
+        score(outcome, game_resources, clock),
+        medal,
+        next_step
+    )
+}
+
+pub fn finish_level(
+    app_state: &mut State<AppState>,
+    game_resources: &mut GameResources,
+    outcome: LevelOutcome,
+) {
+    let state = match outcome {
+        LevelOutcome::Complete(_) => AppState::LevelComplete,
+        LevelOutcome::Failed(_) => AppState::LevelFailed,
+    };
+    // Only the first outcome of a frame counts
+    if app_state.set(state).is_ok() {
+        println!("Level finished: {:?}", outcome);
+        game_resources.outcome = Some(outcome);
+    }
+}
+
+pub fn start_clock(mut clock: ResMut<LevelClock>) {
+    clock.elapsed = 0.;
+}
+
+pub fn tick_clock(
+    time: Res<SimulationTime>,
+    gentle_mode: Res<GentleMode>,
+    mut clock: ResMut<LevelClock>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_resources: ResMut<GameResources>,
+    goals: Query<&GoalZone>,
+) {
+    clock.elapsed += time.delta_seconds();
+    clock.limit = goals
+        .iter()
+        .filter_map(|goal| goal.time_limit)
+        .chain(clock.challenge_limit)
+        .reduce(f32::min);
+    if gentle_mode.enabled {
+        clock.limit = None;
+    }
+    if let Some(limit) = clock.limit {
+        if clock.elapsed > limit {
+            finish_level(
+                &mut app_state,
+                &mut game_resources,
+                LevelOutcome::Failed(FailReason::TimeUp),
+            );
+        }
+    }
+}
+
+pub fn herd_checker(
+    gentle_mode: Res<GentleMode>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_resources: ResMut<GameResources>,
+    players: Query<(), With<Player>>,
+) {
+    let playing = app_state.current() == &AppState::InGame && !gentle_mode.enabled;
+    if !playing || players.iter().count() > 0 {
+        return;
+    }
+    let outcome = herd_outcome(
+        game_resources.delivered_animals_count,
+        game_resources.total_animals_count,
+    );
+    finish_level(&mut app_state, &mut game_resources, outcome);
+}
+
+// How the level ends once nobody is left in the herd
+fn herd_outcome(delivered: i32, total: i32) -> LevelOutcome {
+    if delivered == 0 {
+        LevelOutcome::Failed(FailReason::HerdLost)
+    } else if delivered >= total {
+        LevelOutcome::Complete(CompleteReason::AllDelivered)
+    } else if delivered as f32 >= total as f32 * MIN_DELIVERED_SHARE {
+        LevelOutcome::Complete(CompleteReason::MostDelivered)
+    } else {
+        LevelOutcome::Failed(FailReason::TooFewDelivered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_whole_herd_counts_as_all_delivered() {
+        assert_eq!(
+            herd_outcome(10, 10),
+            LevelOutcome::Complete(CompleteReason::AllDelivered)
+        );
+        assert_eq!(
+            herd_outcome(9, 10),
+            LevelOutcome::Complete(CompleteReason::MostDelivered)
+        );
+        assert_eq!(
+            herd_outcome(5, 10),
+            LevelOutcome::Complete(CompleteReason::MostDelivered)
+        );
+    }
+
+    #[test]
+    fn too_few_or_nobody_delivered_fails() {
+        assert_eq!(
+            herd_outcome(4, 10),
+            LevelOutcome::Failed(FailReason::TooFewDelivered)
+        );
+        assert_eq!(
+            herd_outcome(0, 10),
+            LevelOutcome::Failed(FailReason::HerdLost)
+        );
+    }
+}
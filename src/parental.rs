@@ -0,0 +1,519 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::WindowCloseRequested;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::gentle::GentleMode;
+use crate::ghost::GhostSettings;
+use crate::outcome::{self, CompleteReason, LevelOutcome};
+use crate::resume::{self, LevelProgress};
+use crate::save;
+use crate::{AppState, GameResources};
+
+const GATE_SECONDS: f32 = 3.;
+const WIND_DOWN_SECONDS: f32 = 20.;
+const WIND_DOWN_MIN_SPEED: f32 = 0.3;
+const PLAYTIME_SAVE_SECONDS: f32 = 30.;
+const SESSION_CHOICES: [Option<u32>; 7] = [
+    None,
+    Some(10),
+    Some(15),
+    Some(20),
+    Some(30),
+    Some(45),
+    Some(60),
+];
+const DAILY_CHOICES: [Option<u32>; 6] = [None, Some(30), Some(45), Some(60), Some(90), Some(120)];
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ParentalSettings {
+    pub session_minutes: Option<u32>,
+    pub daily_minutes: Option<u32>,
+    // Settings and quitting need the gate instead of a plain key
+    pub locked: bool,
+}
+
+// Today's play, kept across restarts so the daily cap can't be dodged
+#[derive(Default, Serialize, Deserialize)]
+pub struct Playtime {
+    day: u64,
+    seconds: f32,
+    // How far the wind-down got, so quitting part way through doesn't buy a fresh one
+    wind_down: f32,
+}
+
+#[derive(Default)]
+pub struct Session {
+    elapsed: f32,
+    wind_down: Option<Timer>,
+}
+
+impl Session {
+    // The herd slows to a stroll while the game winds down
+    pub fn speed_factor(&self) -> f32 {
+        match &self.wind_down {
+            Some(timer) => 1. - (1. - WIND_DOWN_MIN_SPEED) * timer.percent(),
+            None => 1.,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct WindDownBanner;
+
+#[derive(Component)]
+pub struct SettingsMenu {
+    selected: usize,
+    // Whatever was going on underneath, eg. a failed load or a reload, stays paused on close
+    physics_was_active: bool,
+}
+
+#[derive(Clone, Copy)]
+enum MenuItem {
+    SessionLimit,
+    DailyLimit,
+    Lock,
+    Gentle,
+    Ghost,
+    NewSession,
+    Quit,
+    Close,
+}
+
+const MENU_ITEMS: [MenuItem; 8] = [
+    MenuItem::SessionLimit,
+    MenuItem::DailyLimit,
+    MenuItem::Lock,
+    MenuItem::Gentle,
+    MenuItem::Ghost,
+    MenuItem::NewSession,
+    MenuItem::Quit,
+    MenuItem::Close,
+];
+
+// Days since the epoch by the local clock, so the daily limit starts over at the player's
+// midnight rather than UTC's
+#[cfg(not(target_arch = "wasm32"))]
+fn today() -> u64 {
+    let now = chrono::Local::now();
+    let local_seconds = now.timestamp() + now.offset().local_minus_utc() as i64;
+    local_seconds.div_euclid(60 * 60 * 24) as u64
+}
+
+#[cfg(target_arch = "wasm32")]
+fn today() -> u64 {
+    let now = js_sys::Date::new_0();
+    // The offset is in minutes, and positive west of UTC
+    let local_millis = now.get_time() - now.get_timezone_offset() * 60. * 1000.;
+    (local_millis / (1000. * 60. * 60. * 24.)).floor() as u64
+}
+
+fn cycle<T: PartialEq + Copy>(choices: &[T], current: T, step: i32) -> T {
+    let index = choices
+        .iter()
+        .position(|&choice| choice == current)
+        .unwrap_or(0) as i32;
+    choices[(index + step).rem_euclid(choices.len() as i32) as usize]
+}
+
+fn minutes_label(minutes: Option<u32>) -> String {
+    match minutes {
+        Some(minutes) => format!("{} min", minutes),
+        None => "Off".to_string(),
+    }
+}
+
+pub fn load_parental(mut commands: Commands) {
+    commands.insert_resource(save::read::<ParentalSettings>("parental.ron").unwrap_or_default());
+    let mut playtime = save::read::<Playtime>("playtime.ron").unwrap_or_default();
+    if playtime.day != today() {
+        playtime = Playtime {
+            day: today(),
+            seconds: 0.,
+            wind_down: 0.,
+        };
+    }
+    commands.insert_resource(playtime);
+}
+
+pub fn play_allowed(settings: &ParentalSettings, session: &Session, playtime: &Playtime) -> bool {
+    let session_ok = settings
+        .session_minutes
+        .is_none_or(|minutes| session.elapsed < minutes as f32 * 60.);
+    let daily_ok = settings
+        .daily_minutes
+        .is_none_or(|minutes| playtime.seconds < minutes as f32 * 60.);
+    session_ok && daily_ok
+}
+
+// Everything play_allowed looks at, for systems that are short on parameters
+#[derive(SystemParam)]
+pub struct PlayLimits<'w, 's> {
+    pub settings: Res<'w, ParentalSettings>,
+    session: Res<'w, Session>,
+    playtime: Res<'w, Playtime>,
+    #[system_param(ignore)]
+    _marker: PhantomData<&'s ()>,
+}
+
+impl PlayLimits<'_, '_> {
+    pub fn play_allowed(&self) -> bool {
+        play_allowed(&self.settings, &self.session, &self.playtime)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn track_playtime(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    settings: Res<ParentalSettings>,
+    mut session: ResMut<Session>,
+    mut playtime: ResMut<Playtime>,
+    mut save_timer: Local<f32>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_resources: ResMut<GameResources>,
+) {
+    if playtime.day != today() {
+        playtime.day = today();
+        playtime.seconds = 0.;
+        playtime.wind_down = 0.;
+    }
+    session.elapsed += time.delta_seconds();
+    playtime.seconds += time.delta_seconds();
+    *save_timer += time.delta_seconds();
+    if *save_timer > PLAYTIME_SAVE_SECONDS {
+        *save_timer = 0.;
+        save::write("playtime.ron", &*playtime);
+    }
+
+    let allowed = play_allowed(&settings, &session, &playtime);
+    if allowed && session.wind_down.is_none() {
+        playtime.wind_down = 0.;
+    }
+    if session.wind_down.is_none() && !allowed {
+        let mut timer = Timer::from_seconds(WIND_DOWN_SECONDS, false);
+        timer.tick(Duration::from_secs_f32(playtime.wind_down));
+        session.wind_down = Some(timer);
+        save::write("playtime.ron", &*playtime);
+        commands
+            .spawn_bundle(
+                TextBundle::from_section(
+                    "The animals are getting sleepy... time to say goodnight!",
+                    TextStyle {
+                        font: asset_server.load("FredokaOne-Regular.ttf"),
+                        font_size: 30.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        bottom: Val::Px(10.0),
+                        left: Val::Px(15.0),
+                        ..default()
+                    },
+                    ..default()
+                }),
+            )
+            .insert(WindDownBanner);
+    }
+    let finished = match &mut session.wind_down {
+        Some(timer) => {
+            timer.tick(time.delta());
+            playtime.wind_down = timer.elapsed_secs();
+            timer.finished()
+        }
+        None => false,
+    };
+    if finished {
+        outcome::finish_level(
+            &mut app_state,
+            &mut game_resources,
+            LevelOutcome::Complete(CompleteReason::Bedtime),
+        );
+    }
+}
+
+pub fn end_wind_down(
+    mut commands: Commands,
+    mut session: ResMut<Session>,
+    playtime: Res<Playtime>,
+    banners: Query<Entity, With<WindDownBanner>>,
+) {
+    session.wind_down = None;
+    save::write("playtime.ron", &*playtime);
+    for entity in banners.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Hold both stick buttons, or both shift keys, to get into the settings. F1 opens them
+// straight away unless they're locked
+pub fn parent_gate(
+    time: Res<Time>,
+    settings: Res<ParentalSettings>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut held: Local<f32>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let can_open = matches!(
+        app_state.current(),
+        AppState::MainMenu
+            | AppState::InGame
+            | AppState::LevelComplete
+            | AppState::LevelFailed
+            | AppState::LoadFailed
+    );
+    if !can_open {
+        *held = 0.;
+        return;
+    }
+    let keys_held = keys.pressed(KeyCode::LShift) && keys.pressed(KeyCode::RShift);
+    let buttons_held = gamepads.iter().any(|&gamepad| {
+        buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::LeftThumb))
+            && buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::RightThumb))
+    });
+    if keys_held || buttons_held {
+        *held += time.delta_seconds();
+    } else {
+        *held = 0.;
+    }
+    let opened = *held > GATE_SECONDS || (!settings.locked && keys.just_pressed(KeyCode::F1));
+    if opened {
+        *held = 0.;
+        if let Err(err) = app_state.push(AppState::ParentSettings) {
+            println!("Couldn't open settings: {:?}", err);
+        }
+    }
+}
+
+fn menu_text(
+    menu: &SettingsMenu,
+    settings: &ParentalSettings,
+    gentle_mode: &GentleMode,
+    ghost_settings: &GhostSettings,
+    playtime: &Playtime,
+) -> String {
+    let lines = MENU_ITEMS.iter().enumerate().map(|(index, item)| {
+        let line = match item {
+            MenuItem::SessionLimit => {
+                format!("Session limit: {}", minutes_label(settings.session_minutes))
+            }
+            MenuItem::DailyLimit => format!(
+                "Daily limit: {} ({} min played today)",
+                minutes_label(settings.daily_minutes),
+                (playtime.seconds / 60.) as u32
+            ),
+            MenuItem::Lock => format!("Lock: {}", if settings.locked { "On" } else { "Off" }),
+            MenuItem::Gentle => format!(
+                "Gentle mode: {}",
+                if gentle_mode.enabled { "On" } else { "Off" }
+            ),
+            MenuItem::Ghost => format!(
+                "Ghost of your best run: {}",
+                if ghost_settings.enabled { "On" } else { "Off" }
+            ),
+            MenuItem::NewSession => "Start a new session".to_string(),
+            MenuItem::Quit => "Quit game".to_string(),
+            MenuItem::Close => "Back to the game".to_string(),
+        };
+        if index == menu.selected {
+            format!("> {} <", line)
+        } else {
+            line
+        }
+    });
+    std::iter::once("Grown-ups only".to_string())
+        .chain(lines)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn open_settings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<ParentalSettings>,
+    gentle_mode: Res<GentleMode>,
+    ghost_settings: Res<GhostSettings>,
+    playtime: Res<Playtime>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    let menu = SettingsMenu {
+        selected: 0,
+        physics_was_active: rapier_config.physics_pipeline_active,
+    };
+    // The herd waits while the grown-ups talk
+    rapier_config.physics_pipeline_active = false;
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::rgba(0., 0., 0., 0.8).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                menu_text(&menu, &settings, &gentle_mode, &ghost_settings, &playtime),
+                TextStyle {
+                    font: asset_server.load("FredokaOne-Regular.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            ));
+        })
+        .insert(menu);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn settings_menu(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut settings: ResMut<ParentalSettings>,
+    mut gentle_mode: ResMut<GentleMode>,
+    mut ghost_settings: ResMut<GhostSettings>,
+    mut session: ResMut<Session>,
+    playtime: Res<Playtime>,
+    mut app_state: ResMut<State<AppState>>,
+    mut exit: EventWriter<AppExit>,
+    progress: LevelProgress,
+    mut menus: Query<(&mut SettingsMenu, &Children, ChangeTrackers<SettingsMenu>)>,
+    mut texts: Query<&mut Text>,
+) {
+    let pressed = |key: KeyCode, button_type: GamepadButtonType| {
+        keys.just_pressed(key)
+            || buttons
+                .get_just_pressed()
+                .any(|button| button.button_type == button_type)
+    };
+    let up = pressed(KeyCode::Up, GamepadButtonType::DPadUp);
+    let down = pressed(KeyCode::Down, GamepadButtonType::DPadDown);
+    let left = pressed(KeyCode::Left, GamepadButtonType::DPadLeft);
+    let right = pressed(KeyCode::Right, GamepadButtonType::DPadRight);
+    let confirm = pressed(KeyCode::Return, GamepadButtonType::South);
+    let back = pressed(KeyCode::Escape, GamepadButtonType::East);
+
+    for (mut menu, children, menu_changes) in menus.iter_mut() {
+        // The press that opened the menu shouldn't also change a setting
+        if menu_changes.is_added() {
+            continue;
+        }
+        if up {
+            menu.selected = (menu.selected + MENU_ITEMS.len() - 1) % MENU_ITEMS.len();
+        }
+        if down {
+            menu.selected = (menu.selected + 1) % MENU_ITEMS.len();
+        }
+        let step = if right || confirm {
+            1
+        } else if left {
+            -1
+        } else {
+            0
+        };
+        let mut close = back;
+        if step != 0 {
+            match MENU_ITEMS[menu.selected] {
+                MenuItem::SessionLimit => {
+                    settings.session_minutes =
+                        cycle(&SESSION_CHOICES, settings.session_minutes, step)
+                }
+                MenuItem::DailyLimit => {
+                    settings.daily_minutes = cycle(&DAILY_CHOICES, settings.daily_minutes, step)
+                }
+                MenuItem::Lock => settings.locked = !settings.locked,
+                MenuItem::Gentle => gentle_mode.enabled = !gentle_mode.enabled,
+                MenuItem::Ghost => ghost_settings.enabled = !ghost_settings.enabled,
+                MenuItem::NewSession if confirm => *session = Session::default(),
+                MenuItem::Quit if confirm => {
+                    if resume::paused_mid_level(&app_state) {
+                        progress.save();
+                    }
+                    save::write("playtime.ron", &*playtime);
+                    exit.send(AppExit)
+                }
+                MenuItem::Close if confirm => close = true,
+                _ => {}
+            }
+        }
+        if close {
+            save::write("parental.ron", &*settings);
+            save::write("ghost.ron", &*ghost_settings);
+            if let Err(err) = app_state.pop() {
+                println!("Couldn't close settings: {:?}", err);
+            }
+            return;
+        }
+        for &child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.sections[0].value =
+                    menu_text(&menu, &settings, &gentle_mode, &ghost_settings, &playtime);
+            }
+        }
+    }
+}
+
+pub fn close_settings(
+    mut commands: Commands,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    menus: Query<(Entity, &SettingsMenu)>,
+) {
+    for (entity, menu) in menus.iter() {
+        rapier_config.physics_pipeline_active = menu.physics_was_active;
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Closing the window is a way of leaving the game too, so it honours the lock
+pub fn close_requested(
+    settings: Res<ParentalSettings>,
+    playtime: Res<Playtime>,
+    app_state: Res<State<AppState>>,
+    progress: LevelProgress,
+    mut requests: EventReader<WindowCloseRequested>,
+    mut windows: ResMut<Windows>,
+) {
+    for request in requests.iter() {
+        if settings.locked {
+            println!("Locked, use the settings to quit");
+            continue;
+        }
+        if resume::paused_mid_level(&app_state) {
+            progress.save();
+        }
+        save::write("playtime.ron", &*playtime);
+        if let Some(window) = windows.get_mut(request.id) {
+            window.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_wraps_both_ways() {
+        assert_eq!(cycle(&SESSION_CHOICES, None, 1), Some(10));
+        assert_eq!(cycle(&SESSION_CHOICES, None, -1), Some(60));
+        assert_eq!(cycle(&SESSION_CHOICES, Some(60), 1), None);
+        assert_eq!(cycle(&DAILY_CHOICES, Some(45), -1), Some(30));
+    }
+
+    #[test]
+    fn cycle_starts_over_from_an_unknown_choice() {
+        assert_eq!(cycle(&DAILY_CHOICES, Some(25), 1), Some(30));
+    }
+}
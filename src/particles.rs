@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+use crate::gentle::Cheer;
+use crate::party::PartyZone;
+use crate::{AnimalFell, AnimalRecruited, Player};
+
+const DUST_MIN_SPEED: f32 = 6.;
+
+#[derive(Deserialize)]
+pub struct EmitterPreset {
+    // Particles per burst, for one-shot emitters
+    count: u32,
+    // Particles per second, for continuous emitters
+    rate: f32,
+    lifetime: f32,
+    min_speed: f32,
+    max_speed: f32,
+    // Half angle in radians of the cone around +Y particles are launched in
+    spread: f32,
+    gravity: f32,
+    size: f32,
+    end_size: f32,
+    height: f32,
+    colors: Vec<(f32, f32, f32)>,
+}
+
+impl EmitterPreset {
+    // Hand edited, so anything emit can't use is clamped, and a preset without colors is rejected
+    fn validated(mut self) -> Result<EmitterPreset, &'static str> {
+        if self.colors.is_empty() {
+            return Err("an emitter preset needs at least one color");
+        }
+        self.rate = self.rate.max(0.);
+        self.lifetime = self.lifetime.max(0.);
+        self.spread = self.spread.max(0.);
+        self.min_speed = self.min_speed.max(0.);
+        self.max_speed = self.max_speed.max(self.min_speed);
+        Ok(self)
+    }
+}
+
+fn validated_preset<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EmitterPreset, D::Error> {
+    EmitterPreset::deserialize(deserializer)?
+        .validated()
+        .map_err(D::Error::custom)
+}
+
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "6b0f4f0c-0d6e-4c57-9f59-1c1c2a8d9d4e"]
+pub struct ParticlePresets {
+    #[serde(deserialize_with = "validated_preset")]
+    recruited: EmitterPreset,
+    #[serde(deserialize_with = "validated_preset")]
+    poof: EmitterPreset,
+    #[serde(deserialize_with = "validated_preset")]
+    dust: EmitterPreset,
+    #[serde(deserialize_with = "validated_preset")]
+    confetti: EmitterPreset,
+}
+
+pub struct ParticleAssets {
+    presets: Handle<ParticlePresets>,
+    mesh: Handle<Mesh>,
+    // Shared per preset and color so bursts don't allocate materials
+    materials: HashMap<(&'static str, usize), Handle<StandardMaterial>>,
+}
+
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec3,
+    gravity: f32,
+    size: f32,
+    end_size: f32,
+    lifetime: Timer,
+}
+
+pub fn setup_particles(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    commands.insert_resource(ParticleAssets {
+        presets: asset_server.load("effects.particles.ron"),
+        mesh: meshes.add(Mesh::from(shape::Icosphere {
+            radius: 0.5,
+            subdivisions: 1,
+        })),
+        materials: HashMap::new(),
+    });
+}
+
+pub fn reload_particle_materials(
+    mut events: EventReader<AssetEvent<ParticlePresets>>,
+    mut particle_assets: ResMut<ParticleAssets>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { .. } = event {
+            particle_assets.materials.clear();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn emit(
+    commands: &mut Commands,
+    rng: &mut impl Rng,
+    particle_assets: &mut ParticleAssets,
+    materials: &mut Assets<StandardMaterial>,
+    name: &'static str,
+    preset: &EmitterPreset,
+    position: Vec3,
+    count: u32,
+) {
+    for _ in 0..count {
+        let color_index = rng.gen_range(0..preset.colors.len());
+        let material = particle_assets
+            .materials
+            .entry((name, color_index))
+            .or_insert_with(|| {
+                let (red, green, blue) = preset.colors[color_index];
+                materials.add(StandardMaterial {
+                    base_color: Color::rgb(red, green, blue),
+                    unlit: true,
+                    ..default()
+                })
+            })
+            .clone();
+        let direction = Quat::from_rotation_y(rng.gen_range(0. ..std::f32::consts::TAU))
+            * Quat::from_rotation_x(rng.gen_range(0. ..=preset.spread))
+            * Vec3::Y;
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: particle_assets.mesh.clone(),
+                material,
+                transform: Transform::from_translation(position + Vec3::Y * preset.height)
+                    .with_scale(Vec3::splat(preset.size)),
+                ..default()
+            })
+            .insert(NotShadowCaster)
+            .insert(Particle {
+                velocity: direction * rng.gen_range(preset.min_speed..=preset.max_speed),
+                gravity: preset.gravity,
+                size: preset.size,
+                end_size: preset.end_size,
+                lifetime: Timer::from_seconds(preset.lifetime, false),
+            });
+    }
+}
+
+pub fn burst_particles(
+    mut commands: Commands,
+    mut particle_assets: ResMut<ParticleAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    presets: Res<Assets<ParticlePresets>>,
+    mut recruited: EventReader<AnimalRecruited>,
+    mut fell: EventReader<AnimalFell>,
+    mut cheers: EventReader<Cheer>,
+) {
+    let presets = match presets.get(&particle_assets.presets) {
+        Some(presets) => presets,
+        None => return,
+    };
+    // Bursts are only for show, so like dust and confetti they stay out of the seeded stream
+    let mut rng = rand::thread_rng();
+    for event in recruited.iter() {
+        emit(
+            &mut commands,
+            &mut rng,
+            &mut particle_assets,
+            &mut materials,
+            "recruited",
+            &presets.recruited,
+            event.position,
+            presets.recruited.count,
+        );
+    }
+    for event in fell.iter() {
+        emit(
+            &mut commands,
+            &mut rng,
+            &mut particle_assets,
+            &mut materials,
+            "poof",
+            &presets.poof,
+            event.respawn_position,
+            presets.poof.count,
+        );
+    }
+    // A few sparkles per animal, the whole herd cheers at once
+    for event in cheers.iter() {
+        emit(
+            &mut commands,
+            &mut rng,
+            &mut particle_assets,
+            &mut materials,
+            "recruited",
+            &presets.recruited,
+            event.position,
+            presets.recruited.count / 4,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn continuous_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particle_assets: ResMut<ParticleAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    presets: Res<Assets<ParticlePresets>>,
+    mut accumulators: Local<HashMap<Entity, f32>>,
+    players: Query<(Entity, &GlobalTransform, &Velocity), With<Player>>,
+    party_zones: Query<(Entity, &GlobalTransform, &PartyZone)>,
+) {
+    let presets = match presets.get(&particle_assets.presets) {
+        Some(presets) => presets,
+        None => return,
+    };
+    let running = players
+        .iter()
+        .filter(|(_, _, velocity)| {
+            Vec2::new(velocity.linvel.x, velocity.linvel.z).length() > DUST_MIN_SPEED
+        })
+        .map(|(entity, transform, _)| (entity, transform, "dust", &presets.dust));
+    let partying = party_zones
+        .iter()
+        .filter(|(_, _, party_zone)| party_zone.guest_count() > 0)
+        .map(|(entity, transform, _)| (entity, transform, "confetti", &presets.confetti));
+    // Dust and confetti follow the frame rate, so they stay out of the seeded stream
+    let mut rng = rand::thread_rng();
+    let mut emitting = Vec::new();
+    for (entity, transform, name, preset) in running.chain(partying) {
+        emitting.push(entity);
+        let accumulator = accumulators.entry(entity).or_insert(0.);
+        *accumulator += preset.rate * time.delta_seconds();
+        let count = accumulator.floor();
+        *accumulator -= count;
+        emit(
+            &mut commands,
+            &mut rng,
+            &mut particle_assets,
+            &mut materials,
+            name,
+            preset,
+            transform.translation(),
+            count as u32,
+        );
+    }
+    accumulators.retain(|entity, _| emitting.contains(entity));
+}
+
+pub fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform)>,
+) {
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        particle.lifetime.tick(time.delta());
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        particle.velocity.y -= particle.gravity * time.delta_seconds();
+        transform.translation += particle.velocity * time.delta_seconds();
+        let progress = particle.lifetime.percent();
+        transform.scale =
+            Vec3::splat(particle.size + (particle.end_size - particle.size) * progress);
+    }
+}
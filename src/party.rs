@@ -0,0 +1,178 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::gltf::GltfExtras;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::gentle::GentleMode;
+use crate::simulation::SimulationTime;
+use crate::{GameResources, PartyAnimal, Player};
+
+const DEFAULT_PARTY_CAPACITY: usize = 10;
+const GO_HOME_SECONDS: f32 = 1.5;
+const GO_HOME_SPEED: f32 = 6.;
+
+#[derive(Component)]
+pub struct PartyZone {
+    bob_position: Vec3,
+    capacity: usize,
+    // Oldest guest first, they're the first to head home when the party gets too full
+    guests: VecDeque<Entity>,
+}
+
+impl PartyZone {
+    // Capacity can be set per zone in Blender with a "capacity" custom property
+    pub fn new(bob_position: Vec3, extras: Option<&GltfExtras>) -> PartyZone {
+        let capacity = extras
+            .and_then(|extras| serde_json::from_str::<serde_json::Value>(&extras.value).ok())
+            .and_then(|value| value.get("capacity")?.as_u64())
+            .map(|capacity| capacity as usize)
+            .unwrap_or(DEFAULT_PARTY_CAPACITY);
+        PartyZone {
+            bob_position,
+            capacity,
+            guests: VecDeque::new(),
+        }
+    }
+
+    pub fn guest_count(&self) -> usize {
+        self.guests.len()
+    }
+
+    // Oldest first
+    pub fn guests(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.guests.iter().copied()
+    }
+
+    pub fn add_guest(&mut self, guest: Entity) {
+        self.guests.push_back(guest);
+    }
+
+    pub fn reset(&mut self) {
+        self.guests.clear();
+    }
+}
+
+#[derive(Component)]
+pub struct GoingHome {
+    timer: Timer,
+    direction: Vec3,
+}
+
+// Gone home, hidden and out of the level but kept around so a restart can bring it back
+#[derive(Component)]
+pub struct Home;
+
+pub fn park(commands: &mut Commands, entity: Entity) {
+    commands
+        .entity(entity)
+        .remove::<GoingHome>()
+        .remove::<PartyAnimal>()
+        .remove::<Player>()
+        .remove::<Collider>()
+        .remove::<RigidBody>()
+        .insert(Velocity::default())
+        .insert(Visibility { is_visible: false })
+        .insert(Home);
+}
+
+pub fn party(time: Res<SimulationTime>, mut party_zone: Query<(&mut Transform, &PartyZone)>) {
+    for (mut transform, party_zone) in party_zone.iter_mut() {
+        let displacement = (time.seconds_in_level() * 7.).sin().powf(1.).abs() * 1.;
+        transform.translation = party_zone.bob_position + Vec3::Y * displacement as f32;
+    }
+}
+
+#[allow(clippy::single_match)]
+pub fn start_the_party(
+    mut commands: Commands,
+    gentle_mode: Res<GentleMode>,
+    mut game_resources: ResMut<GameResources>,
+    mut collisions: EventReader<CollisionEvent>,
+    players: Query<(), With<Player>>,
+    mut party_zones: Query<&mut PartyZone>,
+    transforms: Query<&GlobalTransform>,
+) {
+    // Several contacts can start in the same frame, only count each arrival once
+    let mut arrived = HashSet::new();
+    for collision in collisions.iter() {
+        match collision {
+            &CollisionEvent::Started(a, b, _) => {
+                let (zone_entity, entity) = if party_zones.contains(a) {
+                    (a, b)
+                } else if party_zones.contains(b) {
+                    (b, a)
+                } else {
+                    continue;
+                };
+                if !players.contains(entity) || arrived.contains(&entity) {
+                    continue;
+                }
+                // In gentle mode the last animal stays in the herd so there's always someone to play
+                if gentle_mode.enabled && players.iter().count() - arrived.len() <= 1 {
+                    continue;
+                }
+                arrived.insert(entity);
+                let mut party_zone = party_zones.get_mut(zone_entity).unwrap();
+                while party_zone.guests.len() >= party_zone.capacity {
+                    match party_zone.guests.pop_front() {
+                        Some(guest) => {
+                            if let (Ok(guest_transform), Ok(zone_transform)) =
+                                (transforms.get(guest), transforms.get(zone_entity))
+                            {
+                                let away = (guest_transform.translation()
+                                    - zone_transform.translation())
+                                    * Vec3::new(1., 0., 1.);
+                                send_home(&mut commands, guest, away);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+                party_zone.guests.push_back(entity);
+                game_resources.delivered_animals_count += 1;
+
+                commands
+                    .entity(entity)
+                    .remove::<Player>()
+                    .insert(PartyAnimal {});
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_home(commands: &mut Commands, guest: Entity, away: Vec3) {
+    let direction = if away.length() > f32::EPSILON {
+        away.normalize()
+    } else {
+        Vec3::X
+    };
+    // Leaves the physics world so it can walk off through anything in the way
+    commands
+        .entity(guest)
+        .remove::<PartyAnimal>()
+        .remove::<Collider>()
+        .remove::<RigidBody>()
+        .insert(GoingHome {
+            timer: Timer::from_seconds(GO_HOME_SECONDS, false),
+            direction,
+        });
+}
+
+pub fn go_home(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut animals: Query<(Entity, &mut GoingHome, &mut Transform, &mut Velocity)>,
+) {
+    for (entity, mut going_home, mut transform, mut velocity) in animals.iter_mut() {
+        going_home.timer.tick(time.delta());
+        // Kept up to date so animation and facing treat it like walking
+        velocity.linvel = going_home.direction * GO_HOME_SPEED;
+        transform.translation += velocity.linvel * time.delta_seconds();
+        transform.scale = Vec3::splat(1. - going_home.timer.percent());
+        if going_home.timer.finished() {
+            park(&mut commands, entity);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::species::Species;
+use crate::PartyAnimal;
+
+const PULSE_RATE: f64 = 4.;
+
+pub struct PartyLook {
+    pub tint: Color,
+    pub emissive: Color,
+    pub hat: bool,
+}
+
+pub struct PartyLooks(pub HashMap<Species, PartyLook>);
+
+impl Default for PartyLooks {
+    fn default() -> Self {
+        PartyLooks(HashMap::from([
+            (
+                Species::Puppy,
+                PartyLook {
+                    tint: Color::WHITE,
+                    emissive: Color::rgb(0.2, 0.2, 0.),
+                    hat: true,
+                },
+            ),
+            (
+                Species::Kitty,
+                PartyLook {
+                    tint: Color::rgb(1., 0.8, 1.),
+                    emissive: Color::rgb(0.4, 0., 0.3),
+                    hat: false,
+                },
+            ),
+            (
+                Species::Bunny,
+                PartyLook {
+                    tint: Color::rgb(0.8, 1., 0.8),
+                    emissive: Color::rgb(0., 0.3, 0.1),
+                    hat: true,
+                },
+            ),
+        ]))
+    }
+}
+
+// For species the looks don't cover, when there's no puppy look to borrow either
+const PLAIN_LOOK: PartyLook = PartyLook {
+    tint: Color::WHITE,
+    emissive: Color::rgb(0.2, 0.2, 0.2),
+    hat: false,
+};
+
+impl PartyLooks {
+    fn get(&self, species: &Species) -> &PartyLook {
+        self.0
+            .get(species)
+            .or_else(|| self.0.get(&Species::Puppy))
+            .unwrap_or(&PLAIN_LOOK)
+    }
+}
+
+pub struct PartyVisuals {
+    hat_mesh: Handle<Mesh>,
+    hat_material: Handle<StandardMaterial>,
+    // One party variant per original material and species, shared between every animal
+    variants: HashMap<(Handle<StandardMaterial>, Species), Handle<StandardMaterial>>,
+}
+
+#[derive(Component)]
+pub struct OriginalMaterial(Handle<StandardMaterial>);
+
+#[derive(Component)]
+pub struct PartyHat;
+
+fn scale(color: Color, amount: f32) -> Color {
+    let [red, green, blue, alpha] = color.as_rgba_f32();
+    Color::rgba(red * amount, green * amount, blue * amount, alpha)
+}
+
+fn tint(color: Color, tint: Color) -> Color {
+    let [red, green, blue, alpha] = color.as_rgba_f32();
+    let [tint_red, tint_green, tint_blue, _] = tint.as_rgba_f32();
+    Color::rgba(red * tint_red, green * tint_green, blue * tint_blue, alpha)
+}
+
+pub fn setup_party_visuals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(PartyVisuals {
+        hat_mesh: meshes.add(Mesh::from(shape::Capsule {
+            radius: 0.4,
+            depth: 0.8,
+            ..default()
+        })),
+        hat_material: materials.add(StandardMaterial {
+            base_color: Color::rgb(1., 0.3, 0.6),
+            emissive: Color::rgb(0.3, 0.1, 0.2),
+            ..default()
+        }),
+        variants: HashMap::new(),
+    });
+}
+
+pub fn dress_up_party_animals(
+    mut commands: Commands,
+    looks: Res<PartyLooks>,
+    mut visuals: ResMut<PartyVisuals>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    party_animals: Query<(Entity, &Species, &Children), Added<PartyAnimal>>,
+    material_handles: Query<&Handle<StandardMaterial>, Without<OriginalMaterial>>,
+    hats: Query<(), With<PartyHat>>,
+) {
+    for (entity, species, children) in party_animals.iter() {
+        let look = looks.get(species);
+        let has_hat = children.iter().any(|&child| hats.contains(child));
+        for &child in children.iter() {
+            let original = match material_handles.get(child) {
+                Ok(original) => original.clone(),
+                Err(_) => continue,
+            };
+            let key = (original.clone(), *species);
+            let variant = match visuals.variants.get(&key) {
+                Some(variant) => variant.clone(),
+                None => {
+                    let variant = match materials.get(&original) {
+                        Some(material) => StandardMaterial {
+                            base_color: tint(material.base_color, look.tint),
+                            emissive: look.emissive,
+                            ..material.clone()
+                        },
+                        None => continue,
+                    };
+                    let variant = materials.add(variant);
+                    visuals.variants.insert(key, variant.clone());
+                    variant
+                }
+            };
+            commands
+                .entity(child)
+                .insert(variant)
+                .insert(OriginalMaterial(original));
+        }
+        if look.hat && !has_hat {
+            commands.entity(entity).with_children(|parent| {
+                parent
+                    .spawn_bundle(PbrBundle {
+                        mesh: visuals.hat_mesh.clone(),
+                        material: visuals.hat_material.clone(),
+                        transform: Transform::from_xyz(0., 2.2, 0.),
+                        ..default()
+                    })
+                    .insert(PartyHat);
+            });
+        }
+    }
+}
+
+pub fn pulse_party_materials(
+    time: Res<Time>,
+    looks: Res<PartyLooks>,
+    visuals: Res<PartyVisuals>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let pulse = 0.5 + 0.5 * (time.seconds_since_startup() * PULSE_RATE).sin() as f32;
+    for ((_, species), variant) in visuals.variants.iter() {
+        if let Some(material) = materials.get_mut(variant) {
+            material.emissive = scale(looks.get(species).emissive, pulse);
+        }
+    }
+}
+
+// PartyAnimal is taken off in the simulation, or by commands at the end of Update, and removals
+// are only visible to later stages, so this runs in PostUpdate
+pub fn undress_party_animals(
+    mut commands: Commands,
+    left_party: RemovedComponents<PartyAnimal>,
+    children: Query<&Children>,
+    originals: Query<&OriginalMaterial>,
+    hats: Query<(), With<PartyHat>>,
+) {
+    for entity in left_party.iter() {
+        let children = match children.get(entity) {
+            Ok(children) => children,
+            Err(_) => continue,
+        };
+        for &child in children.iter() {
+            if let Ok(original) = originals.get(child) {
+                commands
+                    .entity(child)
+                    .insert(original.0.clone())
+                    .remove::<OriginalMaterial>();
+            }
+            if hats.contains(child) {
+                commands.entity(child).despawn_recursive();
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use bevy::ecs::system::EntityCommands;
+use bevy::gltf::GltfExtras;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde_json::Value;
+
+use crate::ANIMAL_RADIUS;
+
+// Per object physics set in Blender with custom properties, eg.
+// {"friction": 0.02} for an ice floor, {"restitution": 1.5} for a bouncy mushroom, or
+// {"mass": 3, "gravity_scale": 2, "collider_shape": "capsule", "collider_size": [1.5, 1],
+// "locked_axes": ["rotation", "translation_y"]} on an animal. Anything left out keeps the default
+#[derive(Component, Clone, Default)]
+pub struct PhysicsOverrides {
+    mass: Option<f32>,
+    friction: Option<f32>,
+    restitution: Option<f32>,
+    gravity_scale: Option<f32>,
+    collider: Option<Collider>,
+    locked_axes: Option<LockedAxes>,
+}
+
+fn number(value: &Value, key: &str) -> Option<f32> {
+    value.get(key)?.as_f64().map(|number| number as f32)
+}
+
+// A mass or size of zero or less would break the density and mass calculations, so it's ignored
+// and the default used instead
+fn positive(key: &str, number: f32) -> Option<f32> {
+    if number > 0. {
+        Some(number)
+    } else {
+        println!("Ignoring {} of {}, it has to be above zero", key, number);
+        None
+    }
+}
+
+// "collider_size" is a radius for a ball, half extents for a cuboid and radius then half height
+// for a capsule or cylinder. A single number is used for every dimension
+fn collider(value: &Value) -> Option<Collider> {
+    let sizes = match value.get("collider_size") {
+        Some(Value::Array(sizes)) => sizes
+            .iter()
+            .filter_map(|size| size.as_f64())
+            .map(|size| size as f32)
+            .collect(),
+        Some(size) => size
+            .as_f64()
+            .map(|size| vec![size as f32])
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let sizes = sizes
+        .into_iter()
+        .map(|size| positive("collider_size", size))
+        .collect::<Vec<_>>();
+    let size = |index: usize| {
+        sizes
+            .get(index)
+            .or_else(|| sizes.first())
+            .copied()
+            .flatten()
+            .unwrap_or(ANIMAL_RADIUS)
+    };
+    let shape = match value.get("collider_shape") {
+        Some(shape) => shape.as_str()?,
+        // A size on its own resizes the default ball
+        None if !sizes.is_empty() => "ball",
+        None => return None,
+    };
+    match shape {
+        "ball" => Some(Collider::ball(size(0))),
+        "cuboid" => Some(Collider::cuboid(size(0), size(1), size(2))),
+        "capsule" => Some(Collider::capsule_y(size(1), size(0))),
+        "cylinder" => Some(Collider::cylinder(size(1), size(0))),
+        _ => {
+            println!("Unknown collider_shape: {}", shape);
+            None
+        }
+    }
+}
+
+fn locked_axes(value: &Value) -> Option<LockedAxes> {
+    let axes = value.get("locked_axes")?.as_array()?;
+    Some(
+        axes.iter()
+            .filter_map(|axis| axis.as_str())
+            .fold(LockedAxes::empty(), |locked, axis| {
+                locked
+                    | match axis {
+                        "rotation" => LockedAxes::ROTATION_LOCKED,
+                        "rotation_x" => LockedAxes::ROTATION_LOCKED_X,
+                        "rotation_y" => LockedAxes::ROTATION_LOCKED_Y,
+                        "rotation_z" => LockedAxes::ROTATION_LOCKED_Z,
+                        "translation" => LockedAxes::TRANSLATION_LOCKED,
+                        "translation_x" => LockedAxes::TRANSLATION_LOCKED_X,
+                        "translation_y" => LockedAxes::TRANSLATION_LOCKED_Y,
+                        "translation_z" => LockedAxes::TRANSLATION_LOCKED_Z,
+                        _ => {
+                            println!("Unknown locked axis: {}", axis);
+                            LockedAxes::empty()
+                        }
+                    }
+            }),
+    )
+}
+
+impl PhysicsOverrides {
+    pub fn from_extras(extras: Option<&GltfExtras>) -> PhysicsOverrides {
+        let value = extras
+            .and_then(|extras| serde_json::from_str::<Value>(&extras.value).ok())
+            .unwrap_or_default();
+        PhysicsOverrides {
+            mass: number(&value, "mass").and_then(|mass| positive("mass", mass)),
+            friction: number(&value, "friction"),
+            restitution: number(&value, "restitution"),
+            gravity_scale: number(&value, "gravity_scale"),
+            collider: collider(&value),
+            locked_axes: locked_axes(&value),
+        }
+    }
+
+    fn apply_coefficients(
+        &self,
+        entity: &mut EntityCommands,
+        combine_rule: CoefficientCombineRule,
+    ) {
+        if let Some(coefficient) = self.friction {
+            entity.insert(Friction {
+                coefficient,
+                combine_rule,
+            });
+        }
+        if let Some(coefficient) = self.restitution {
+            entity.insert(Restitution {
+                coefficient,
+                combine_rule,
+            });
+        }
+    }
+
+    // What a surface is like to touch, for level geometry. Max outranks the Average animals use,
+    // so whichever of the two grips more or is bouncier wins. Animals have no friction of their
+    // own, so an ice floor is slippery while a rough slope holds them
+    pub fn apply_surface(&self, entity: &mut EntityCommands) {
+        self.apply_coefficients(entity, CoefficientCombineRule::Max);
+    }
+
+    // Goes on after animal_physics so it replaces the defaults. An animal's friction and
+    // restitution average with whatever it touches, so they don't override every surface
+    pub fn apply_body(&self, entity: &mut EntityCommands) {
+        self.apply_coefficients(entity, CoefficientCombineRule::Average);
+        let collider = self
+            .collider
+            .clone()
+            .unwrap_or_else(|| Collider::ball(ANIMAL_RADIUS));
+        if let Some(mass) = self.mass {
+            // Rapier wants a density, this is the one that gives the mass at the authored size
+            let volume = collider.raw.mass_properties(1.).mass();
+            entity.insert(ColliderMassProperties::Density(mass / volume));
+        }
+        if let Some(gravity_scale) = self.gravity_scale {
+            entity.insert(GravityScale(gravity_scale));
+        }
+        if let Some(locked_axes) = self.locked_axes {
+            entity.insert(locked_axes);
+        }
+        if self.collider.is_some() {
+            entity.insert(collider);
+        }
+        entity.insert(self.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+
+    fn parse(json: &str) -> PhysicsOverrides {
+        PhysicsOverrides::from_extras(Some(&GltfExtras {
+            value: json.to_string(),
+        }))
+    }
+
+    #[test]
+    fn reads_every_override() {
+        let overrides = parse(
+            r#"{"mass": 3, "friction": 0.02, "restitution": 1.5, "gravity_scale": 2,
+                "collider_shape": "cuboid", "collider_size": [1, 2, 3],
+                "locked_axes": ["rotation", "translation_y"]}"#,
+        );
+        assert_eq!(overrides.mass, Some(3.));
+        assert_eq!(overrides.friction, Some(0.02));
+        assert_eq!(overrides.restitution, Some(1.5));
+        assert_eq!(overrides.gravity_scale, Some(2.));
+        let collider = overrides.collider.unwrap();
+        assert_eq!(
+            collider.as_cuboid().unwrap().half_extents(),
+            Vec3::new(1., 2., 3.)
+        );
+        assert_eq!(
+            overrides.locked_axes,
+            Some(LockedAxes::ROTATION_LOCKED | LockedAxes::TRANSLATION_LOCKED_Y)
+        );
+    }
+
+    #[test]
+    fn missing_or_broken_extras_keep_the_defaults() {
+        for overrides in [
+            PhysicsOverrides::from_extras(None),
+            parse("not json"),
+            parse(r#"{"name": "Rock"}"#),
+        ] {
+            assert_eq!(overrides.mass, None);
+            assert_eq!(overrides.friction, None);
+            assert_eq!(overrides.restitution, None);
+            assert!(overrides.collider.is_none());
+            assert!(overrides.locked_axes.is_none());
+        }
+    }
+
+    #[test]
+    fn a_size_on_its_own_resizes_the_ball() {
+        let collider = parse(r#"{"collider_size": 2}"#).collider.unwrap();
+        assert_eq!(collider.as_ball().unwrap().radius(), 2.);
+        let capsule = parse(r#"{"collider_shape": "capsule", "collider_size": [0.5, 1.5]}"#)
+            .collider
+            .unwrap();
+        let capsule = capsule.as_capsule().unwrap();
+        assert_eq!(capsule.radius(), 0.5);
+        assert_eq!(capsule.half_height(), 1.5);
+    }
+
+    #[test]
+    fn only_level_geometry_wins_the_combine() {
+        let overrides = parse(r#"{"friction": 0.1, "restitution": 0.8}"#);
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let mut surface = commands.spawn();
+        overrides.apply_surface(&mut surface);
+        let surface = surface.id();
+        let mut body = commands.spawn();
+        overrides.apply_body(&mut body);
+        let body = body.id();
+        queue.apply(&mut world);
+        for (entity, combine_rule) in [
+            (surface, CoefficientCombineRule::Max),
+            (body, CoefficientCombineRule::Average),
+        ] {
+            let friction = world.get::<Friction>(entity).unwrap();
+            assert_eq!(
+                (friction.coefficient, friction.combine_rule),
+                (0.1, combine_rule)
+            );
+            let restitution = world.get::<Restitution>(entity).unwrap();
+            assert_eq!(
+                (restitution.coefficient, restitution.combine_rule),
+                (0.8, combine_rule)
+            );
+        }
+    }
+
+    #[test]
+    fn masses_and_sizes_below_zero_fall_back_to_the_defaults() {
+        let overrides =
+            parse(r#"{"mass": 0, "collider_shape": "cuboid", "collider_size": [2, -1, 0]}"#);
+        assert_eq!(overrides.mass, None);
+        let collider = overrides.collider.unwrap();
+        assert_eq!(
+            collider.as_cuboid().unwrap().half_extents(),
+            Vec3::new(2., ANIMAL_RADIUS, ANIMAL_RADIUS)
+        );
+        assert!(parse(r#"{"collider_shape": "teapot"}"#).collider.is_none());
+    }
+}
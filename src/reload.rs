@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::loading::LevelReady;
+use crate::outcome::LevelClock;
+use crate::party::PartyZone;
+use crate::restart::LevelSnapshot;
+use crate::resume::{self, SavedAnimals, SavedLevel};
+use crate::{AppState, Collectable, GameResources};
+
+// A re-exported level being swapped in. The old scene stays, with physics paused, until the new
+// one is connected so there's a herd the whole time
+pub struct PendingReload {
+    saved: SavedLevel,
+    old_scene: Entity,
+    new_scene: Entity,
+    // The new scene's entities, once it's ready
+    entities: Option<Vec<Entity>>,
+}
+
+// Bevy doesn't respawn a glTF scene when the file changes, so this spawns the new version and
+// carries the gameplay over by name once it's connected
+#[allow(clippy::too_many_arguments)]
+pub fn watch_level_reload(
+    mut commands: Commands,
+    mut scene_events: EventReader<AssetEvent<Scene>>,
+    app_state: Res<State<AppState>>,
+    pending: Option<Res<PendingReload>>,
+    mut game_resources: ResMut<GameResources>,
+    clock: Res<LevelClock>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    scenes: Query<&Handle<Scene>>,
+    animals: SavedAnimals,
+    zones: Query<(&Name, &PartyZone)>,
+) {
+    let modified = scene_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let old_scene = match game_resources.scene_entity {
+        Some(scene) => scene,
+        None => return,
+    };
+    let handle = match scenes.get(old_scene) {
+        Ok(handle) => handle.clone(),
+        Err(_) => return,
+    };
+    // Only once the level is connected, and not while the settings have physics paused
+    let connected = matches!(
+        app_state.current(),
+        AppState::InGame | AppState::LevelComplete | AppState::LevelFailed
+    );
+    if !modified.contains(&handle) || !connected || pending.is_some() {
+        return;
+    }
+    println!(
+        "{}.gltf changed, reconnecting",
+        game_resources.current_level
+    );
+    let saved = resume::capture_level(&game_resources, &clock, &animals, &zones);
+    // connect_from_scene counts and snapshots the new scene's animals
+    game_resources.total_animals_count = 0;
+    *snapshot = LevelSnapshot::default();
+    rapier_config.physics_pipeline_active = false;
+    let new_scene = commands
+        .spawn_bundle(SceneBundle {
+            scene: handle,
+            ..default()
+        })
+        .id();
+    game_resources.scene_entity = Some(new_scene);
+    commands.insert_resource(PendingReload {
+        saved,
+        old_scene,
+        new_scene,
+        entities: None,
+    });
+}
+
+pub fn finish_reload(
+    mut commands: Commands,
+    pending: Option<ResMut<PendingReload>>,
+    mut level_ready: EventReader<LevelReady>,
+    app_state: Res<State<AppState>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    animals: Query<(Entity, &Name), With<Collectable>>,
+    mut zones: Query<(&Name, &mut PartyZone)>,
+) {
+    let mut pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+    // connect_from_scene reacts to LevelReady in the same frame, its components are only there
+    // from the next one
+    if pending.entities.is_none() {
+        let new_scene = pending.new_scene;
+        pending.entities = level_ready
+            .iter()
+            .find(|ready| ready.scene == new_scene)
+            .map(|ready| ready.entities.clone());
+        return;
+    }
+    // Settings opened in the meantime keep physics paused, the herd carries over once they close
+    let connected = matches!(
+        app_state.current(),
+        AppState::InGame | AppState::LevelComplete | AppState::LevelFailed
+    );
+    if !connected {
+        return;
+    }
+    let entities = pending.entities.take().unwrap_or_default();
+    let guests =
+        resume::restore_animals(&pending.saved, &mut commands, animals.iter_many(&entities));
+    for &entity in entities.iter() {
+        if let Ok((name, mut zone)) = zones.get_mut(entity) {
+            for &guest in guests.get(name.as_str()).into_iter().flatten() {
+                zone.add_guest(guest);
+            }
+        }
+    }
+    commands.entity(pending.old_scene).despawn_recursive();
+    commands.remove_resource::<PendingReload>();
+    rapier_config.physics_pipeline_active = true;
+    println!("Level reconnected");
+}
+
+// The re-exported level failed to connect, or the level was left before it did. Whatever is
+// left of the new scene goes with the level, the old one has to be cleared up here
+pub fn abandon_reload(
+    mut commands: Commands,
+    pending: Option<Res<PendingReload>>,
+    app_state: Res<State<AppState>>,
+    game_resources: Res<GameResources>,
+) {
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+    let left = game_resources.scene_entity != Some(pending.new_scene);
+    if !left && app_state.current() != &AppState::LoadFailed {
+        return;
+    }
+    commands.entity(pending.old_scene).despawn_recursive();
+    commands.remove_resource::<PendingReload>();
+    println!("Level reload abandoned");
+}
@@ -0,0 +1,220 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::ChallengeSettings;
+use crate::gentle::GentleMode;
+use crate::save;
+use crate::simulation::{self, GameRng, SimulationTime};
+use crate::GameResources;
+
+// Version 2 stores one input per simulation tick rather than per rendered frame, version 3 adds
+// the wind-down speed
+const REPLAY_VERSION: u32 = 3;
+
+// Everything that steers the herd in one simulation tick
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrameInput {
+    // Post-deadzone stick/keys direction before the camera rotation, x and z
+    pub movement: [f32; 2],
+    // Any button went down since the last tick
+    pub cheer: bool,
+    // Slows the herd while the session winds down, see parental::Session::speed_factor
+    pub speed: f32,
+}
+
+impl Default for FrameInput {
+    fn default() -> Self {
+        FrameInput {
+            movement: [0., 0.],
+            cheer: false,
+            speed: 1.,
+        }
+    }
+}
+
+impl FrameInput {
+    pub fn movement(&self) -> Vec3 {
+        Vec3::new(self.movement[0], 0., self.movement[1])
+    }
+}
+
+// Identical frames are stored once with a repeat count, holding a direction or standing
+// still compresses to almost nothing
+#[derive(Serialize, Deserialize)]
+struct ReplayFile {
+    version: u32,
+    level: String,
+    seed: u64,
+    gentle: bool,
+    timed: bool,
+    runs: Vec<(u32, FrameInput)>,
+}
+
+pub enum ReplayMode {
+    Off,
+    Record,
+    Playback(PathBuf),
+}
+
+pub struct Replayer {
+    pub mode: ReplayMode,
+    seed: u64,
+    frames: Vec<FrameInput>,
+    cursor: usize,
+}
+
+impl Default for Replayer {
+    fn default() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let playback = args
+            .iter()
+            .position(|arg| arg == "--replay")
+            .and_then(|index| args.get(index + 1));
+        let mode = match playback {
+            Some(path) => ReplayMode::Playback(PathBuf::from(path)),
+            None if args.iter().any(|arg| arg == "--record") => ReplayMode::Record,
+            None => ReplayMode::Off,
+        };
+        Replayer {
+            mode,
+            seed: 0,
+            frames: Vec::new(),
+            cursor: 0,
+        }
+    }
+}
+
+impl Replayer {
+    // Plays back input that didn't come from a replay file, eg. the determinism test's script
+    #[cfg(test)]
+    pub fn playback(frames: Vec<FrameInput>) -> Replayer {
+        Replayer {
+            mode: ReplayMode::Playback(PathBuf::new()),
+            seed: 0,
+            frames,
+            cursor: 0,
+        }
+    }
+
+    // Records the live input, or swaps it for the recorded one when playing back
+    pub fn frame_input(&mut self, in_game: bool, live: FrameInput) -> FrameInput {
+        match self.mode {
+            ReplayMode::Off => live,
+            ReplayMode::Record => {
+                if in_game {
+                    self.frames.push(live);
+                }
+                live
+            }
+            ReplayMode::Playback(_) => {
+                if !in_game {
+                    return FrameInput::default();
+                }
+                if self.cursor == self.frames.len() {
+                    println!("Replay finished after {} ticks", self.frames.len());
+                }
+                self.cursor += 1;
+                self.frames
+                    .get(self.cursor - 1)
+                    .copied()
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ReadInput;
+
+pub fn load_replay(
+    mut replayer: ResMut<Replayer>,
+    mut game_resources: ResMut<GameResources>,
+    mut gentle_mode: ResMut<GentleMode>,
+    mut challenge_settings: ResMut<ChallengeSettings>,
+) {
+    let path = match &replayer.mode {
+        ReplayMode::Playback(path) => path.clone(),
+        _ => return,
+    };
+    let file = std::fs::read(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| {
+            bincode::deserialize::<ReplayFile>(&bytes).map_err(|err| err.to_string())
+        });
+    match file {
+        Ok(file) if file.version == REPLAY_VERSION => {
+            println!("Playing back {:?} on {}", path, file.level);
+            replayer.seed = file.seed;
+            replayer.frames = file
+                .runs
+                .into_iter()
+                .flat_map(|(count, frame)| std::iter::repeat_n(frame, count as usize))
+                .collect();
+            game_resources.current_level = file.level;
+            gentle_mode.enabled = file.gentle;
+            challenge_settings.timed = file.timed;
+        }
+        Ok(file) => println!("Can't play replay version {}", file.version),
+        Err(err) => println!("Couldn't load replay {:?}: {}", path, err),
+    }
+}
+
+pub fn start_replay(
+    mut replayer: ResMut<Replayer>,
+    mut rng: ResMut<GameRng>,
+    mut time: ResMut<SimulationTime>,
+) {
+    if let ReplayMode::Record = replayer.mode {
+        replayer.seed = simulation::seed_from_args().unwrap_or_else(rand::random);
+        replayer.frames.clear();
+    }
+    replayer.cursor = 0;
+    rng.0 = StdRng::seed_from_u64(replayer.seed);
+    // The party zones bob along with the level's own clock, so they're in the same place on
+    // every run
+    time.start_level();
+}
+
+pub fn finish_replay(
+    replayer: Res<Replayer>,
+    game_resources: Res<GameResources>,
+    gentle_mode: Res<GentleMode>,
+    challenge_settings: Res<ChallengeSettings>,
+) {
+    if !matches!(replayer.mode, ReplayMode::Record) || cfg!(target_arch = "wasm32") {
+        return;
+    }
+    let mut runs: Vec<(u32, FrameInput)> = Vec::new();
+    for &frame in replayer.frames.iter() {
+        match runs.last_mut() {
+            Some((count, last)) if *last == frame => *count += 1,
+            _ => runs.push((1, frame)),
+        }
+    }
+    let file = ReplayFile {
+        version: REPLAY_VERSION,
+        level: game_resources.current_level.clone(),
+        seed: replayer.seed,
+        gentle: gentle_mode.enabled,
+        timed: challenge_settings.timed,
+        runs,
+    };
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let path = save::save_path("replays").join(format!("{}-{}.replay", file.level, stamp));
+    let result = std::fs::create_dir_all(save::save_path("replays"))
+        .map_err(|err| err.to_string())
+        .and_then(|_| bincode::serialize(&file).map_err(|err| err.to_string()))
+        .and_then(|bytes| std::fs::write(&path, bytes).map_err(|err| err.to_string()));
+    match result {
+        Ok(_) => println!("Saved replay {:?}", path),
+        Err(err) => println!("Couldn't save replay {:?}: {}", path, err),
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::goal::GoalZone;
+use crate::levels::{LevelManifest, Levels};
+use crate::party::{GoingHome, Home, PartyZone};
+use crate::physics::PhysicsOverrides;
+use crate::simulation::Interpolated;
+use crate::{animal_physics, facing, parental, AppState, GameResources, PartyAnimal, Player};
+
+// How the animals were placed when the level was connected. Restarting puts everything back
+// in place rather than loading the scene again, so it's instant and doesn't wait on assets
+#[derive(Default)]
+pub struct LevelSnapshot {
+    animals: Vec<(Entity, Transform, bool)>,
+}
+
+impl LevelSnapshot {
+    pub fn record_animal(&mut self, entity: Entity, transform: &Transform, player: bool) {
+        self.animals.push((entity, *transform, player));
+    }
+}
+
+// Despawns the level so another one, or the main menu, can take its place
+pub fn leave_level(commands: &mut Commands, game_resources: &mut GameResources) {
+    if let Some(scene) = game_resources.scene_entity.take() {
+        commands.entity(scene).despawn_recursive();
+    }
+    if let Some(ui_node) = game_resources.ui_node.take() {
+        commands.entity(ui_node).despawn_recursive();
+    }
+    game_resources.delivered_animals_count = 0;
+    game_resources.total_animals_count = 0;
+    game_resources.herd_home_count = 0;
+    game_resources.outcome = None;
+}
+
+// On the end screen Esc/B goes back to the main menu, Right moves on to the next level after a
+// win, and any other key or button plays the level again
+#[allow(clippy::too_many_arguments)]
+pub fn end_screen_choice(
+    mut commands: Commands,
+    mut game_resources: ResMut<GameResources>,
+    mut app_state: ResMut<State<AppState>>,
+    buttons: Res<Input<GamepadButton>>,
+    keys: Res<Input<KeyCode>>,
+    levels: Res<Levels>,
+    manifests: Res<Assets<LevelManifest>>,
+    parental_settings: Res<parental::ParentalSettings>,
+    session: Res<parental::Session>,
+    playtime: Res<parental::Playtime>,
+    snapshot: Res<LevelSnapshot>,
+    bodies: Query<(), With<RigidBody>>,
+    overrides: Query<&PhysicsOverrides>,
+    mut party_zones: Query<&mut PartyZone>,
+    mut goals: Query<&mut GoalZone>,
+) {
+    // Out of playtime means the end screen stays until a grown-up changes the limits
+    if !parental::play_allowed(&parental_settings, &session, &playtime) {
+        return;
+    }
+    if buttons.get_just_pressed().count() == 0 && keys.get_just_pressed().count() == 0 {
+        return;
+    }
+    let pressed = |key: KeyCode, button_type: GamepadButtonType| {
+        keys.just_pressed(key)
+            || buttons
+                .get_just_pressed()
+                .any(|button| button.button_type == button_type)
+    };
+    if pressed(KeyCode::Escape, GamepadButtonType::East) {
+        match app_state.set(AppState::MainMenu) {
+            Ok(_) => leave_level(&mut commands, &mut game_resources),
+            Err(err) => println!("Couldn't go back to the main menu: {:?}", err),
+        }
+        return;
+    }
+    let won = app_state.current() == &AppState::LevelComplete;
+    let next_level = levels
+        .next(&manifests, &game_resources.current_level)
+        .map(|level| level.name.clone());
+    if let Some(next_level) = next_level.filter(|_| won) {
+        if pressed(KeyCode::Right, GamepadButtonType::DPadRight) {
+            match app_state.set(AppState::Loading) {
+                Ok(_) => {
+                    leave_level(&mut commands, &mut game_resources);
+                    game_resources.current_level = next_level;
+                }
+                Err(err) => println!("Couldn't start the next level: {:?}", err),
+            }
+            return;
+        }
+    }
+    // Another change already queued this frame wins, eg. the settings opening on F1
+    if let Err(err) = app_state.set(AppState::InGame) {
+        println!("Couldn't restart the level: {:?}", err);
+        return;
+    }
+    for &(entity, transform, player) in snapshot.animals.iter() {
+        let mut animal = commands.entity(entity);
+        animal
+            .remove::<Player>()
+            .remove::<PartyAnimal>()
+            .remove::<GoingHome>()
+            .remove::<Home>()
+            .insert(transform)
+            .insert(Interpolated::new(&transform))
+            .insert(facing::Facing::new(&transform))
+            .insert(Velocity::default())
+            .insert(Visibility::default());
+        // Animals that went home left the physics world
+        if !bodies.contains(entity) {
+            animal.insert_bundle(animal_physics());
+            if let Ok(overrides) = overrides.get(entity) {
+                overrides.apply_body(&mut animal);
+            }
+        }
+        if player {
+            animal.insert(Player {
+                spawn_position: transform.translation,
+            });
+        }
+    }
+    for mut party_zone in party_zones.iter_mut() {
+        party_zone.reset();
+    }
+    for mut goal in goals.iter_mut() {
+        goal.reset();
+    }
+    // The scene isn't connected again, so the animal total still stands
+    game_resources.delivered_animals_count = 0;
+    game_resources.herd_home_count = 0;
+    game_resources.outcome = None;
+}
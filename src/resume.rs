@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::WindowCloseRequested;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::facing::Facing;
+use crate::outcome::LevelClock;
+use crate::party::{self, GoingHome, Home, PartyZone};
+use crate::save;
+use crate::simulation::Interpolated;
+use crate::{AppState, Collectable, GameResources, PartyAnimal, Player};
+
+const RESUME_FILE: &str = "resume.ron";
+const AUTOSAVE_SECONDS: f32 = 5.;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum Role {
+    Collectable,
+    Player { spawn_position: [f32; 3] },
+    PartyAnimal,
+    // Went home from the party, or was on the way
+    Home,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedAnimal {
+    role: Role,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    linvel: [f32; 3],
+    angvel: [f32; 3],
+    // Heading and lean, otherwise turn_animals snaps the rotation back on the first tick
+    facing: ([f32; 4], f32),
+}
+
+// A level in progress. Animals are matched up by their node name from the glTF, anything
+// missing from the save is left as the level placed it
+#[derive(Serialize, Deserialize)]
+pub struct SavedLevel {
+    level: String,
+    elapsed: f32,
+    delivered_animals_count: i32,
+    total_animals_count: i32,
+    herd_home_count: i32,
+    animals: HashMap<String, SavedAnimal>,
+    // Party zone name to guest names, oldest first
+    guests: HashMap<String, Vec<String>>,
+}
+
+#[derive(Default)]
+pub struct ResumeOffer {
+    saved: Option<SavedLevel>,
+}
+
+impl ResumeOffer {
+    // The main menu offers to continue this level
+    pub fn level(&self) -> Option<&str> {
+        self.saved.as_ref().map(|saved| saved.level.as_str())
+    }
+
+    // Picks the saved level back up, restore_level applies it once play starts
+    pub fn continue_game(&mut self, commands: &mut Commands, game_resources: &mut GameResources) {
+        if let Some(saved) = self.saved.take() {
+            game_resources.current_level = saved.level.clone();
+            commands.insert_resource(PendingRestore(saved));
+        }
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct CheckResume;
+
+// Taken by restore_level when the player picks Continue
+pub struct PendingRestore(SavedLevel);
+
+pub type SavedAnimals<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Name,
+        &'static Interpolated,
+        &'static Velocity,
+        &'static Facing,
+        Option<&'static Player>,
+        Option<&'static PartyAnimal>,
+        Option<&'static GoingHome>,
+        Option<&'static Home>,
+    ),
+    With<Collectable>,
+>;
+
+// Everything a save of the level in progress is made from
+#[derive(SystemParam)]
+pub(crate) struct LevelProgress<'w, 's> {
+    game_resources: Res<'w, GameResources>,
+    clock: Res<'w, LevelClock>,
+    animals: SavedAnimals<'w, 's>,
+    zones: Query<'w, 's, (&'static Name, &'static PartyZone)>,
+}
+
+impl<'w, 's> LevelProgress<'w, 's> {
+    pub fn save(&self) {
+        let saved = capture_level(
+            &self.game_resources,
+            &self.clock,
+            &self.animals,
+            &self.zones,
+        );
+        save::write(RESUME_FILE, &saved);
+    }
+}
+
+// The settings are open on top of a level, autosave doesn't run then so leaving the game from
+// there has to save by itself
+pub fn paused_mid_level(app_state: &State<AppState>) -> bool {
+    app_state.current() == &AppState::ParentSettings
+        && app_state.inactives().last() == Some(&AppState::InGame)
+}
+
+fn clear_save() {
+    save::remove(RESUME_FILE);
+}
+
+pub fn check_resume(mut offer: ResMut<ResumeOffer>) {
+    *offer = ResumeOffer {
+        saved: save::read::<SavedLevel>(RESUME_FILE),
+    };
+}
+
+// Runs after the clock is started so the restored time sticks
+pub fn restore_level(
+    mut commands: Commands,
+    pending: Option<Res<PendingRestore>>,
+    mut game_resources: ResMut<GameResources>,
+    mut clock: ResMut<LevelClock>,
+    animals: Query<(Entity, &Name), With<Collectable>>,
+    mut zones: Query<(&Name, &mut PartyZone)>,
+) {
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+    let saved = &pending.0;
+    commands.remove_resource::<PendingRestore>();
+    clock.elapsed = saved.elapsed;
+    game_resources.delivered_animals_count = saved.delivered_animals_count;
+    game_resources.total_animals_count = saved.total_animals_count;
+    game_resources.herd_home_count = saved.herd_home_count;
+
+    let guests = restore_animals(saved, &mut commands, animals.iter());
+    for (name, mut zone) in zones.iter_mut() {
+        for &guest in guests.get(name.as_str()).into_iter().flatten() {
+            zone.add_guest(guest);
+        }
+    }
+    println!("Restored {} at {:.1}s", saved.level, saved.elapsed);
+}
+
+// Puts each animal back the way it was saved, matched up by name. Returns the guests for each
+// party zone by zone name, for the caller to hand to whichever zones it's restoring
+pub fn restore_animals<'a>(
+    saved: &SavedLevel,
+    commands: &mut Commands,
+    animals: impl Iterator<Item = (Entity, &'a Name)>,
+) -> HashMap<String, Vec<Entity>> {
+    let mut entities = HashMap::new();
+    for (entity, name) in animals {
+        let animal = match saved.animals.get(name.as_str()) {
+            Some(animal) => animal,
+            None => continue,
+        };
+        if animal.role == Role::Home {
+            party::park(commands, entity);
+            continue;
+        }
+        entities.insert(name.as_str().to_string(), entity);
+        let transform = Transform {
+            translation: Vec3::from(animal.translation),
+            rotation: Quat::from_array(animal.rotation),
+            scale: Vec3::from(animal.scale),
+        };
+        let mut entity_commands = commands.entity(entity);
+        entity_commands
+            .insert(transform)
+            .insert(Interpolated::new(&transform))
+            .insert(Facing::restored(
+                Quat::from_array(animal.facing.0),
+                animal.facing.1,
+            ))
+            .insert(Velocity {
+                linvel: Vec3::from(animal.linvel),
+                angvel: Vec3::from(animal.angvel),
+            })
+            .remove::<Player>()
+            .remove::<PartyAnimal>();
+        match animal.role {
+            Role::Collectable => {}
+            Role::Player { spawn_position } => {
+                entity_commands.insert(Player {
+                    spawn_position: Vec3::from(spawn_position),
+                });
+            }
+            Role::PartyAnimal => {
+                entity_commands.insert(PartyAnimal {});
+            }
+            Role::Home => {}
+        }
+    }
+    saved
+        .guests
+        .iter()
+        .map(|(zone, guests)| {
+            (
+                zone.clone(),
+                guests
+                    .iter()
+                    .filter_map(|guest| entities.get(guest).copied())
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+pub fn capture_level(
+    game_resources: &GameResources,
+    clock: &LevelClock,
+    animals: &SavedAnimals,
+    zones: &Query<(&Name, &PartyZone)>,
+) -> SavedLevel {
+    let names = animals
+        .iter()
+        .map(|(entity, name, ..)| (entity, name.as_str().to_string()))
+        .collect::<HashMap<_, _>>();
+    SavedLevel {
+        level: game_resources.current_level.clone(),
+        elapsed: clock.elapsed,
+        delivered_animals_count: game_resources.delivered_animals_count,
+        total_animals_count: game_resources.total_animals_count,
+        herd_home_count: game_resources.herd_home_count,
+        animals: animals
+            .iter()
+            .map(
+                |(
+                    _,
+                    name,
+                    interpolated,
+                    velocity,
+                    facing,
+                    player,
+                    party_animal,
+                    going_home,
+                    home,
+                )| {
+                    // The simulated transform, not whatever was interpolated for the last frame
+                    let transform = interpolated.current();
+                    let role = match (player, party_animal) {
+                        _ if going_home.is_some() || home.is_some() => Role::Home,
+                        (_, Some(_)) => Role::PartyAnimal,
+                        (Some(player), None) => Role::Player {
+                            spawn_position: player.spawn_position.to_array(),
+                        },
+                        (None, None) => Role::Collectable,
+                    };
+                    (
+                        name.as_str().to_string(),
+                        SavedAnimal {
+                            role,
+                            translation: transform.translation.to_array(),
+                            rotation: transform.rotation.to_array(),
+                            scale: transform.scale.to_array(),
+                            linvel: velocity.linvel.to_array(),
+                            angvel: velocity.angvel.to_array(),
+                            facing: {
+                                let (heading, tilt) = facing.heading_and_tilt();
+                                (heading.to_array(), tilt)
+                            },
+                        },
+                    )
+                },
+            )
+            .collect(),
+        guests: zones
+            .iter()
+            .map(|(name, zone)| {
+                (
+                    name.as_str().to_string(),
+                    zone.guests()
+                        .filter_map(|guest| names.get(&guest).cloned())
+                        .collect(),
+                )
+            })
+            .collect(),
+    }
+}
+
+// Saves every few seconds, and when the window is closed, so a crash or a closed tab loses
+// almost nothing
+pub fn autosave(
+    time: Res<Time>,
+    mut since_save: Local<f32>,
+    mut close_requests: EventReader<WindowCloseRequested>,
+    mut exits: EventReader<AppExit>,
+    progress: LevelProgress,
+) {
+    *since_save += time.delta_seconds();
+    let closing = close_requests.iter().count() > 0 || exits.iter().count() > 0;
+    if *since_save > AUTOSAVE_SECONDS || closing {
+        *since_save = 0.;
+        progress.save();
+    }
+}
+
+// A finished level has nothing to continue
+pub fn discard_save(mut offer: ResMut<ResumeOffer>) {
+    offer.saved = None;
+    clear_save();
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::Medal;
+
+const SAVE_DIR: &str = "saves";
+
+pub fn save_path(file_name: &str) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(file_name)
+}
+
+// Saves are files in the save directory on desktop. The browser has no file system, so there
+// they go to local storage instead, one key per file
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    use super::save_path;
+
+    pub fn read_bytes(file_name: &str) -> Option<Vec<u8>> {
+        std::fs::read(save_path(file_name)).ok()
+    }
+
+    pub fn write_bytes(file_name: &str, bytes: &[u8]) -> Result<(), String> {
+        let path = save_path(file_name);
+        path.parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, bytes))
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn remove(file_name: &str) {
+        let _ = std::fs::remove_file(save_path(file_name));
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    use web_sys::Storage;
+
+    use super::SAVE_DIR;
+
+    fn local_storage() -> Option<Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    fn key(file_name: &str) -> String {
+        format!("animal-aggregator/{}/{}", SAVE_DIR, file_name)
+    }
+
+    // Local storage only holds strings, so bytes are kept as hex
+    pub fn read_bytes(file_name: &str) -> Option<Vec<u8>> {
+        let hex = local_storage()?.get_item(&key(file_name)).ok()??;
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+            .collect()
+    }
+
+    pub fn write_bytes(file_name: &str, bytes: &[u8]) -> Result<(), String> {
+        let hex = bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        local_storage()
+            .ok_or_else(|| "local storage isn't available".to_string())?
+            .set_item(&key(file_name), &hex)
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    pub fn remove(file_name: &str) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(&key(file_name));
+        }
+    }
+}
+
+pub use storage::{read_bytes, remove, write_bytes};
+
+pub fn read<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let bytes = read_bytes(file_name)?;
+    match ron::de::from_bytes(&bytes) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            println!("Ignoring broken save {}: {}", file_name, err);
+            None
+        }
+    }
+}
+
+pub fn write<T: Serialize>(file_name: &str, value: &T) {
+    let result = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::new())
+        .map_err(|err| err.to_string())
+        .and_then(|text| write_bytes(file_name, text.as_bytes()));
+    if let Err(err) = result {
+        println!("Couldn't save {}: {}", file_name, err);
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Progress {
+    pub medals: HashMap<String, Medal>,
+    pub best_times: HashMap<String, f32>,
+}
+
+pub fn load_progress(mut commands: Commands) {
+    commands.insert_resource(read::<Progress>("progress.ron").unwrap_or_default());
+}
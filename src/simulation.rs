@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::time::FixedTimesteps;
+use bevy::transform::TransformSystem;
+use bevy_rapier3d::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+// Gameplay and physics advance in steps of exactly this long, whatever the frame rate
+pub const TICK_SECONDS: f64 = 1. / 60.;
+const TIMESTEP: &str = "simulation";
+
+#[derive(StageLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Simulation;
+
+#[derive(StageLabel, Debug, Clone, Eq, PartialEq, Hash)]
+enum SimulationStage {
+    Restore,
+    Gameplay,
+    Snapshot,
+}
+
+// Game time as far as the simulation is concerned, use this instead of `Time` in anything that
+// runs on the fixed timestep
+#[derive(Default)]
+pub struct SimulationTime {
+    // Ticks played in the current level. Paused ticks don't count, so the level plays out the same
+    // however long the menus and loading took
+    tick: u64,
+}
+
+impl SimulationTime {
+    pub fn delta(&self) -> Duration {
+        Duration::from_secs_f64(TICK_SECONDS)
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        TICK_SECONDS as f32
+    }
+
+    pub fn seconds_in_level(&self) -> f64 {
+        self.tick as f64 * TICK_SECONDS
+    }
+
+    pub fn start_level(&mut self) {
+        self.tick = 0;
+    }
+}
+
+// Button presses between ticks, so a press is seen by exactly one tick however many run in
+// the frame
+#[derive(Default)]
+pub struct ButtonLatch {
+    pub cheer: bool,
+}
+
+// Randomness that has to come out the same for the same seed, ie. in replays
+pub struct GameRng(pub StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng(StdRng::seed_from_u64(
+            seed_from_args().unwrap_or_else(rand::random),
+        ))
+    }
+}
+
+pub fn seed_from_args() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == "--seed")
+        .and_then(|index| args.get(index + 1))
+        .and_then(|seed| seed.parse().ok())
+}
+
+// Where a body was on the last two ticks, rendering blends between them
+#[derive(Component)]
+pub struct Interpolated {
+    previous: Transform,
+    current: Transform,
+}
+
+impl Interpolated {
+    pub fn new(transform: &Transform) -> Interpolated {
+        Interpolated {
+            previous: *transform,
+            current: *transform,
+        }
+    }
+
+    pub fn current(&self) -> &Transform {
+        &self.current
+    }
+}
+
+// Rapier runs inside the simulation schedule instead of once per frame
+pub fn add_physics(app: &mut App) {
+    app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false))
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: TICK_SECONDS as f32,
+                substeps: 1,
+            },
+            ..default()
+        })
+        .add_stage_before(
+            CoreStage::Last,
+            PhysicsStages::DetectDespawn,
+            SystemStage::parallel().with_system_set(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsStages::DetectDespawn),
+            ),
+        )
+        .init_resource::<SimulationTime>()
+        .init_resource::<ButtonLatch>()
+        .init_resource::<GameRng>();
+}
+
+// The settings menu, level reloads and loading pause physics. Gameplay waits along with it, so a
+// paused tick doesn't move the herd or get left out of a replay
+fn unpaused(rapier_config: Res<RapierConfiguration>) -> ShouldRun {
+    if rapier_config.physics_pipeline_active {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+// One tick: put bodies back where the simulation left them, step physics, run gameplay on the
+// results, then remember where everything ended up. Gameplay is single threaded so systems
+// touching the same components always run in the same order
+pub fn schedule(gameplay: SystemStage) -> Schedule {
+    let physics_stage = |stage: PhysicsStages| {
+        SystemStage::parallel()
+            .with_run_criteria(unpaused)
+            .with_system_set(RapierPhysicsPlugin::<NoUserData>::get_systems(stage))
+    };
+    Schedule::default()
+        .with_stage(
+            SimulationStage::Restore,
+            SystemStage::parallel().with_system(restore_transforms),
+        )
+        .with_stage(
+            PhysicsStages::SyncBackend,
+            physics_stage(PhysicsStages::SyncBackend),
+        )
+        .with_stage(
+            PhysicsStages::StepSimulation,
+            physics_stage(PhysicsStages::StepSimulation),
+        )
+        .with_stage(
+            PhysicsStages::Writeback,
+            physics_stage(PhysicsStages::Writeback),
+        )
+        .with_stage(
+            SimulationStage::Gameplay,
+            gameplay.with_run_criteria(unpaused),
+        )
+        .with_stage(
+            SimulationStage::Snapshot,
+            SystemStage::parallel().with_system(snapshot_transforms),
+        )
+}
+
+pub fn add_simulation(app: &mut App, gameplay: SystemStage) {
+    app.add_stage_after(
+        CoreStage::Update,
+        Simulation,
+        schedule(gameplay)
+            .with_run_criteria(bevy::time::FixedTimestep::step(TICK_SECONDS).with_label(TIMESTEP)),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        interpolate_transforms.before(TransformSystem::TransformPropagate),
+    );
+}
+
+// Nothing ticks while a level loads, however long that takes, so a recording and its playback
+// both start from the herd the level placed, on the first tick in play
+pub fn pause_for_loading(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = false;
+}
+
+pub fn start_playing(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = true;
+}
+
+pub fn latch_buttons(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut latch: ResMut<ButtonLatch>,
+) {
+    if keys.get_just_pressed().count() > 0 || buttons.get_just_pressed().count() > 0 {
+        latch.cheer = true;
+    }
+}
+
+// The rendered pose has already reached GlobalTransform, which is what rapier reads user changes
+// from, so both go back to where the simulation left the body. Bodies sit at the top of their
+// scene, their parents are never interpolated
+fn restore_transforms(
+    parents: Query<&GlobalTransform, Without<Interpolated>>,
+    mut bodies: Query<(
+        &Interpolated,
+        &mut Transform,
+        &mut GlobalTransform,
+        Option<&Parent>,
+    )>,
+) {
+    for (interpolated, mut transform, mut global_transform, parent) in bodies.iter_mut() {
+        if *transform != interpolated.current {
+            *transform = interpolated.current;
+        }
+        let restored = match parent.and_then(|parent| parents.get(parent.get()).ok()) {
+            Some(parent) => parent.mul_transform(interpolated.current),
+            None => GlobalTransform::from(interpolated.current),
+        };
+        if *global_transform != restored {
+            *global_transform = restored;
+        }
+    }
+}
+
+fn snapshot_transforms(
+    rapier_config: Res<RapierConfiguration>,
+    mut time: ResMut<SimulationTime>,
+    mut bodies: Query<(&mut Interpolated, &Transform)>,
+) {
+    if rapier_config.physics_pipeline_active {
+        time.tick += 1;
+    }
+    for (mut interpolated, transform) in bodies.iter_mut() {
+        interpolated.previous = interpolated.current;
+        interpolated.current = *transform;
+    }
+}
+
+fn interpolate_transforms(
+    timesteps: Res<FixedTimesteps>,
+    mut bodies: Query<(&Interpolated, &mut Transform)>,
+) {
+    if let Some(state) = timesteps.get(TIMESTEP) {
+        interpolate(state.overstep_percentage() as f32, &mut bodies);
+    }
+}
+
+// Puts bodies `alpha` of the way from the previous tick to the current one, for rendering only
+pub fn interpolate(alpha: f32, bodies: &mut Query<(&Interpolated, &mut Transform)>) {
+    for (interpolated, mut transform) in bodies.iter_mut() {
+        let (previous, current) = (interpolated.previous, interpolated.current);
+        if previous == current {
+            continue;
+        }
+        *transform = Transform {
+            translation: previous.translation.lerp(current.translation, alpha),
+            rotation: previous.rotation.slerp(current.rotation, alpha),
+            scale: previous.scale.lerp(current.scale, alpha),
+        };
+    }
+}
@@ -0,0 +1,31 @@
+use bevy::prelude::*;
+
+// Blender object names look like "Collectable.Bunny.004" or "Player.Puppy", the species is the
+// second dotted segment.
+#[derive(Component, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Species {
+    Puppy,
+    Kitty,
+    Bunny,
+    Unknown,
+}
+
+impl Species {
+    pub fn from_name(name: &str) -> Species {
+        match name.split('.').nth(1) {
+            Some("Puppy") | Some("Dog") => Species::Puppy,
+            Some("Kitty") | Some("Cat") => Species::Kitty,
+            Some("Bunny") | Some("Rabbit") => Species::Bunny,
+            _ => Species::Unknown,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Species::Puppy => "puppy",
+            Species::Kitty => "kitty",
+            Species::Bunny => "bunny",
+            Species::Unknown => "animal",
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use bevy::gltf::{Gltf, GltfExtras};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+// How steep a contact can be and still count as standing on it
+const GROUND_NORMAL_Y: f32 = 0.5;
+const TRAMPOLINE_SPEED: f32 = 25.;
+
+// What level geometry is made of, set in Blender with a "surface" custom property, eg.
+// {"surface": "ice"}, or by having the name in the material, eg. "Frozen Ice"
+#[derive(Component, Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum Surface {
+    #[default]
+    Grass,
+    Ice,
+    Mud,
+    Water,
+    Trampoline,
+}
+
+pub struct SurfaceParams {
+    pub speed: f32,
+    // How quickly the herd gets up to speed and slows back down, per second. Infinite is
+    // instant, which is how the herd moves on grass and in the air
+    pub acceleration: f32,
+    pub deceleration: f32,
+    // Upward speed given to anything landing on it
+    pub bounce: Option<f32>,
+}
+
+impl Surface {
+    // Matches whole words only, so "Frozen Ice" or "Ice.001" are ice but "Rice" isn't
+    fn from_label(label: &str) -> Option<Surface> {
+        let label = label.to_lowercase();
+        let words = label
+            .split(|character: char| !character.is_alphanumeric())
+            .collect::<Vec<_>>();
+        [
+            ("grass", Surface::Grass),
+            ("ice", Surface::Ice),
+            ("mud", Surface::Mud),
+            ("water", Surface::Water),
+            ("trampoline", Surface::Trampoline),
+        ]
+        .into_iter()
+        .find(|(name, _)| words.contains(name))
+        .map(|(_, surface)| surface)
+    }
+
+    pub fn from_extras(extras: Option<&GltfExtras>) -> Option<Surface> {
+        let value = serde_json::from_str::<serde_json::Value>(&extras?.value).ok()?;
+        let label = value.get("surface")?.as_str()?;
+        let surface = Surface::from_label(label);
+        if surface.is_none() {
+            println!("Unknown surface: {}", label);
+        }
+        surface
+    }
+
+    pub fn params(&self) -> SurfaceParams {
+        match self {
+            Surface::Grass => SurfaceParams {
+                speed: 1.,
+                acceleration: f32::INFINITY,
+                deceleration: f32::INFINITY,
+                bounce: None,
+            },
+            Surface::Ice => SurfaceParams {
+                speed: 1.2,
+                acceleration: 2.,
+                deceleration: 0.6,
+                bounce: None,
+            },
+            Surface::Mud => SurfaceParams {
+                speed: 0.5,
+                acceleration: 8.,
+                deceleration: f32::INFINITY,
+                bounce: None,
+            },
+            Surface::Water => SurfaceParams {
+                speed: 0.6,
+                acceleration: 4.,
+                deceleration: 4.,
+                bounce: None,
+            },
+            Surface::Trampoline => SurfaceParams {
+                speed: 1.,
+                acceleration: f32::INFINITY,
+                deceleration: f32::INFINITY,
+                bounce: Some(TRAMPOLINE_SPEED),
+            },
+        }
+    }
+}
+
+// Surfaces named by the level's materials, for geometry without a "surface" property
+pub fn material_surfaces(gltf: Option<&Gltf>) -> HashMap<Handle<StandardMaterial>, Surface> {
+    gltf.map(|gltf| {
+        gltf.named_materials
+            .iter()
+            .filter_map(|(name, material)| Some((material.clone(), Surface::from_label(name)?)))
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+// What an animal is standing on, None in the air
+#[derive(Component, Default)]
+pub struct Footing {
+    pub surface: Option<Surface>,
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DetectFooting;
+
+// Runs on the simulation tick after physics, so it sees this tick's contacts. Anything untagged
+// underfoot, including another animal, counts as grass
+pub fn detect_footing(
+    rapier_context: Res<RapierContext>,
+    surfaces: Query<&Surface>,
+    mut animals: Query<(Entity, &mut Footing)>,
+) {
+    for (entity, mut footing) in animals.iter_mut() {
+        let mut ground = None;
+        for contact in rapier_context.contacts_with(entity) {
+            if !contact.has_any_active_contacts() {
+                continue;
+            }
+            // Normals point from the first collider to the second
+            let (other, up) = if contact.collider1() == entity {
+                (contact.collider2(), -1.)
+            } else {
+                (contact.collider1(), 1.)
+            };
+            let underfoot = contact
+                .manifolds()
+                .any(|manifold| manifold.normal().y * up > GROUND_NORMAL_Y);
+            if underfoot {
+                let surface = surfaces.get(other).copied().unwrap_or_default();
+                // Tagged ground wins over plain ground when standing across both
+                if ground.is_none() || ground == Some(Surface::Grass) {
+                    ground = Some(surface);
+                }
+            }
+        }
+        footing.surface = ground;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_match_whole_words_in_any_case() {
+        assert_eq!(Surface::from_label("ice"), Some(Surface::Ice));
+        assert_eq!(Surface::from_label("Frozen Ice"), Some(Surface::Ice));
+        assert_eq!(Surface::from_label("Ice.001"), Some(Surface::Ice));
+        assert_eq!(Surface::from_label("MUD"), Some(Surface::Mud));
+        assert_eq!(
+            Surface::from_label("Trampoline_Red"),
+            Some(Surface::Trampoline)
+        );
+    }
+
+    #[test]
+    fn words_containing_a_surface_name_dont_match() {
+        for label in ["Rice", "Dice", "Slice", "Grassland", "Watery", ""] {
+            assert_eq!(Surface::from_label(label), None, "{}", label);
+        }
+    }
+}